@@ -0,0 +1,447 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+use crate::{
+    config_resolver::{resolve_config, ConfigResolveError},
+    config_validator::LineIndex,
+    etl_config_parser::EtlConfig,
+};
+
+const INCLUDE_KEY: &str = "include";
+/// Extensions of config files, the rest of a directory include is skipped
+const CONFIG_EXTENSIONS: [&str; 4] = ["yaml", "yml", "json", "toml"];
+
+lazy_static! {
+    /// `${VAR}` or `${VAR:-default}`, `$${` is a literal `${`
+    static ref RX_ENV: Regex = Regex::new(r"\$(\$?)\{([A-Za-z_][A-Za-z0-9_]*)(?::-([^}]*))?\}").expect("couldn't parse env regex");
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigLoadError {
+    #[error("{}: couldn't read {source}", .file.display())]
+    Io { file: PathBuf, source: std::io::Error },
+    #[error("{}: couldn't parse {message}", .file.display())]
+    Parse { file: PathBuf, message: String },
+    #[error("{}: {key}: {message}", .file.display())]
+    Key { file: PathBuf, key: String, message: String },
+    #[error("{}: {source}", .file.display())]
+    Resolve { file: PathBuf, source: ConfigResolveError },
+}
+
+/// Files of parts of the document by their path like `sources[1]`, for errors
+#[derive(Debug, Default)]
+struct Origins(Vec<(String, PathBuf)>);
+
+impl Origins {
+    fn add(&mut self, path: &str, file: &Path) {
+        self.0.push((path.to_owned(), file.to_owned()));
+    }
+
+    /// File of the longest prefix of the path
+    fn file_of<'o>(&'o self, path: &str, root: &'o Path) -> &'o Path {
+        self.0
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(root, |(_, file)| file.as_path())
+    }
+}
+
+fn child_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn key_text(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        other => serde_yaml::to_string(other).unwrap_or_default().trim().to_owned(),
+    }
+}
+
+fn parse_document(file: &Path) -> Result<Value, ConfigLoadError> {
+    let text = fs::read_to_string(file).map_err(|source| ConfigLoadError::Io {
+        file: file.to_owned(),
+        source,
+    })?;
+    let parse_error = |message: String| ConfigLoadError::Parse {
+        file: file.to_owned(),
+        message,
+    };
+    match file.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|err| parse_error(err.to_string())),
+        Some("toml") => toml::from_str(&text).map_err(|err| parse_error(err.to_string())),
+        _ => serde_yaml::from_str(&text).map_err(|err| parse_error(err.to_string())),
+    }
+}
+
+/// `${VAR:-default}` replaced in every string, the result is a string whatever the value
+/// of the variable looks like
+fn interpolate(value: Value, path: &str, file: &Path) -> Result<Value, ConfigLoadError> {
+    match value {
+        Value::String(text) if text.contains('$') => {
+            let mut missing = None;
+            let replaced = RX_ENV.replace_all(&text, |caps: &Captures| {
+                if !caps[1].is_empty() {
+                    return caps[0][1..].to_owned();
+                }
+                match (env::var(&caps[2]), caps.get(3)) {
+                    (Ok(value), _) => value,
+                    (Err(_), Some(default)) => default.as_str().to_owned(),
+                    (Err(_), None) => {
+                        missing.get_or_insert_with(|| caps[2].to_owned());
+                        String::new()
+                    }
+                }
+            });
+            if let Some(name) = missing {
+                return Err(ConfigLoadError::Key {
+                    file: file.to_owned(),
+                    key: path.to_owned(),
+                    message: format!("environment variable [{name}] is not set and has no default"),
+                });
+            }
+            Ok(Value::String(replaced.into_owned()))
+        }
+        Value::Mapping(mapping) => {
+            let mut interpolated = Mapping::new();
+            for (key, value) in mapping {
+                let value = interpolate(value, &child_path(path, &key_text(&key)), file)?;
+                interpolated.insert(key, value);
+            }
+            Ok(Value::Mapping(interpolated))
+        }
+        Value::Sequence(items) => items
+            .into_iter()
+            .enumerate()
+            .map(|(idx, item)| interpolate(item, &format!("{path}[{idx}]"), file))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Sequence),
+        value => Ok(value),
+    }
+}
+
+/// Mappings are merged key by key, lists are concatenated, other values of `over` win
+fn merge(base: Value, over: Value) -> Value {
+    match (base, over) {
+        (Value::Mapping(mut base), Value::Mapping(over)) => {
+            for (key, over_value) in over {
+                let value = match base.remove(&key) {
+                    Some(base_value) => merge(base_value, over_value),
+                    None => over_value,
+                };
+                base.insert(key, value);
+            }
+            Value::Mapping(base)
+        }
+        (Value::Sequence(mut base), Value::Sequence(over)) => {
+            base.extend(over);
+            Value::Sequence(base)
+        }
+        (_, over) => over,
+    }
+}
+
+struct Loader {
+    /// files being loaded, for cycles
+    stack: Vec<PathBuf>,
+    origins: Origins,
+}
+
+impl Loader {
+    /// Files of `include`, a directory stands for its config files ordered by name
+    fn include_files(&self, include: &Value, path: &str, file: &Path) -> Result<Vec<PathBuf>, ConfigLoadError> {
+        let key_error = |message: String| ConfigLoadError::Key {
+            file: file.to_owned(),
+            key: path.to_owned(),
+            message,
+        };
+        let names: Vec<&str> = match include {
+            Value::String(name) => vec![name.as_str()],
+            Value::Sequence(names) => names
+                .iter()
+                .map(|name| name.as_str().ok_or_else(|| key_error(format!("include [{name:?}] isn't a path"))))
+                .collect::<Result<_, _>>()?,
+            other => return Err(key_error(format!("include [{other:?}] isn't a path or a list of paths"))),
+        };
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let mut files = Vec::new();
+        for name in names {
+            let target = dir.join(name);
+            if !target.is_dir() {
+                files.push(target);
+                continue;
+            }
+            let entries = fs::read_dir(&target).map_err(|err| key_error(format!("{}: {err}", target.display())))?;
+            let mut dir_files: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|entry| {
+                    entry.is_file()
+                        && entry
+                            .extension()
+                            .and_then(|extension| extension.to_str())
+                            .is_some_and(|extension| CONFIG_EXTENSIONS.contains(&extension))
+                })
+                .collect();
+            dir_files.sort();
+            files.extend(dir_files);
+        }
+        Ok(files)
+    }
+
+    /// Document of the file handed to `expand` while the file is open, `including` is the file
+    /// with the `include` for the error of a cycle
+    fn with_file<T>(
+        &mut self,
+        file: &Path,
+        including: &Path,
+        expand: impl FnOnce(&mut Self, Value) -> Result<T, ConfigLoadError>,
+    ) -> Result<T, ConfigLoadError> {
+        let canonical = file.canonicalize().map_err(|source| ConfigLoadError::Io {
+            file: file.to_owned(),
+            source,
+        })?;
+        if self.stack.contains(&canonical) {
+            return Err(ConfigLoadError::Key {
+                file: including.to_owned(),
+                key: INCLUDE_KEY.to_owned(),
+                message: format!("{} includes itself", file.display()),
+            });
+        }
+        self.stack.push(canonical);
+        let document = interpolate(parse_document(file)?, "", file)?;
+        let expanded = expand(self, document);
+        self.stack.pop();
+        expanded
+    }
+
+    /// `include` of a mapping merged in, keys of the mapping win
+    fn expand_mapping(&mut self, mut mapping: Mapping, path: &str, file: &Path) -> Result<Value, ConfigLoadError> {
+        let included = match mapping.remove(INCLUDE_KEY) {
+            Some(include) => self.include_files(&include, &child_path(path, INCLUDE_KEY), file)?,
+            None => Vec::new(),
+        };
+        let mut merged = Value::Mapping(Mapping::new());
+        for included_file in included {
+            let document = self.with_file(&included_file, file, |loader, document| {
+                let Value::Mapping(document) = document else {
+                    return Err(ConfigLoadError::Parse {
+                        file: included_file.clone(),
+                        message: format!("document included into [{path}] must be a mapping"),
+                    });
+                };
+                for key in document.keys() {
+                    loader.origins.add(&child_path(path, &key_text(key)), &included_file);
+                }
+                loader.expand_mapping(document, path, &included_file)
+            })?;
+            merged = merge(merged, document);
+        }
+        let mut expanded = Mapping::new();
+        for (key, value) in mapping {
+            let key_path = child_path(path, &key_text(&key));
+            if merged.get(&key).is_some() {
+                self.origins.add(&key_path, file);
+            }
+            let value = self.expand(value, &key_path, file)?;
+            expanded.insert(key, value);
+        }
+        Ok(merge(merged, Value::Mapping(expanded)))
+    }
+
+    /// An item which is only an `include` is replaced by the included documents,
+    /// items of included lists are spliced
+    fn expand_items(&mut self, items: Vec<Value>, path: &str, file: &Path, expanded: &mut Vec<Value>) -> Result<(), ConfigLoadError> {
+        for item in items {
+            let include = match &item {
+                Value::Mapping(mapping) if mapping.len() == 1 => mapping.get(INCLUDE_KEY).cloned(),
+                _ => None,
+            };
+            let Some(include) = include else {
+                let item_path = format!("{path}[{}]", expanded.len());
+                self.origins.add(&item_path, file);
+                expanded.push(self.expand(item, &item_path, file)?);
+                continue;
+            };
+            let include_path = format!("{path}[{}].{INCLUDE_KEY}", expanded.len());
+            for included_file in self.include_files(&include, &include_path, file)? {
+                self.with_file(&included_file, file, |loader, document| {
+                    let documents = match document {
+                        Value::Sequence(documents) => documents,
+                        document => vec![document],
+                    };
+                    loader.expand_items(documents, path, &included_file, expanded)
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn expand(&mut self, value: Value, path: &str, file: &Path) -> Result<Value, ConfigLoadError> {
+        match value {
+            Value::Mapping(mapping) => self.expand_mapping(mapping, path, file),
+            Value::Sequence(items) => {
+                let mut expanded = Vec::with_capacity(items.len());
+                self.expand_items(items, path, file, &mut expanded)?;
+                Ok(Value::Sequence(expanded))
+            }
+            value => Ok(value),
+        }
+    }
+}
+
+fn load_with_origins(file: &Path) -> Result<(Value, Origins), ConfigLoadError> {
+    let mut loader = Loader {
+        stack: Vec::new(),
+        origins: Origins::default(),
+    };
+    let document = loader.with_file(file, file, |loader, document| loader.expand(document, "", file))?;
+    let document = resolve_config(document).map_err(|source| ConfigLoadError::Resolve {
+        file: file.to_owned(),
+        source,
+    })?;
+    Ok((document, loader.origins))
+}
+
+/// Config document of a YAML, JSON or TOML file by its extension with `include`, environment
+/// variables, fragments and `extends` resolved
+pub fn load_config_value(file: &Path) -> Result<Value, ConfigLoadError> {
+    load_with_origins(file).map(|(document, _)| document)
+}
+
+/// Deserialization errors name the key and the file it comes from
+pub fn load_config(file: &Path) -> Result<EtlConfig, ConfigLoadError> {
+    let (document, origins) = load_with_origins(file)?;
+    let err = match serde_yaml::from_value(document.clone()) {
+        Ok(etl_config) => return Ok(etl_config),
+        Err(err) => err,
+    };
+    // errors of values have no location, the document as text has one
+    let text = serde_yaml::to_string(&document).unwrap_or_default();
+    let key = serde_yaml::from_str::<EtlConfig>(&text)
+        .err()
+        .and_then(|err| err.location())
+        .and_then(|location| LineIndex::parse(&text).path_at(location.line()))
+        .unwrap_or_default();
+    Err(ConfigLoadError::Key {
+        file: origins.file_of(&key, file).to_owned(),
+        key,
+        message: err.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, text: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_load_config() {
+        let dir = env::temp_dir().join(format!("test_load_config_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        env::set_var("TEST_LOAD_CONFIG_AGENT", "agent/1.0");
+        env::set_var("TEST_LOAD_CONFIG_LANG", "123");
+        env::set_var("TEST_LOAD_CONFIG_REFERER", "x: y # z");
+        let root = write(
+            &dir,
+            "etl.yaml",
+            r#"
+include: http.toml
+sources:
+  - include: sources/
+  - name: inline
+    root_url: 'https://${TEST_LOAD_CONFIG_HOST:-inline.com}'
+    menu: { page_limit: 1, default_url: /, page_url_sub: '?p=\1', first_page_url: '', rules: [] }
+    card: { rules: [] }
+"#,
+        );
+        write(
+            &dir,
+            "http.toml",
+            r#"
+[http.retries]
+max_retries = 3
+backoff_factor = 2
+status_forcelist = [500]
+timeout = 5
+
+[http.headers]
+user-agent = "${TEST_LOAD_CONFIG_AGENT}"
+accept-language = "${TEST_LOAD_CONFIG_LANG}"
+referer = "${TEST_LOAD_CONFIG_REFERER}"
+dnt = "${TEST_LOAD_CONFIG_DNT:-true}"
+"#,
+        );
+        let source = |name: &str| {
+            format!(
+                r#"{{ "name": "{name}", "root_url": "https://{name}.com", "card": {{ "rules": [] }},
+                "menu": {{ "page_limit": 1, "default_url": "/", "page_url_sub": "", "first_page_url": "", "rules": [] }} }}"#
+            )
+        };
+        write(&dir, "sources/a.json", &source("a"));
+        write(&dir, "sources/b.yaml", &source("b"));
+        write(&dir, "sources/notes.txt", "not a config");
+
+        let config = load_config(&root).unwrap();
+        let names: Vec<&str> = config.sources.iter().map(|source| source.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", "inline"]);
+        assert_eq!(config.sources[2].root_url, "https://inline.com");
+        assert_eq!(config.http.retries.max_retries, 3);
+        assert_eq!(config.http.headers["user-agent"], "agent/1.0");
+        assert_eq!(config.http.headers["accept-language"], "123");
+        assert_eq!(config.http.headers["referer"], "x: y # z");
+        assert_eq!(config.http.headers["dnt"], "true");
+
+        write(&dir, "sources/b.yaml", &source("b").replace(r#""page_limit": 1"#, r#""page_limit": "many""#));
+        let err = load_config(&root).unwrap_err();
+        assert!(matches!(&err, ConfigLoadError::Key { file, key, .. }
+            if file.ends_with("sources/b.yaml") && key == "sources[1].menu.page_limit"), "{err}");
+
+        write(&dir, "sources/b.yaml", &source("b").replace("https://b.com", "${TEST_LOAD_CONFIG_MISSING}"));
+        let err = load_config(&root).unwrap_err();
+        assert!(matches!(&err, ConfigLoadError::Key { file, key, .. }
+            if file.ends_with("sources/b.yaml") && key == "root_url"), "{err}");
+
+        write(&dir, "sources/b.yaml", "include: ../etl.yaml");
+        let err = load_config(&root).unwrap_err();
+        assert!(err.to_string().contains("includes itself"), "{err}");
+
+        write(&dir, "sources/b.yaml", "name: [b");
+        assert!(matches!(load_config(&root), Err(ConfigLoadError::Parse { file, .. }) if file.ends_with("sources/b.yaml")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_interpolate() {
+        env::set_var("TEST_INTERPOLATE_PORT", "8123");
+        let file = Path::new("etl.yaml");
+        let value = |text: &str| interpolate(Value::String(text.into()), "key", file).unwrap();
+        assert_eq!(value("${TEST_INTERPOLATE_PORT}"), Value::from("8123"));
+        assert_eq!(value("http://h:${TEST_INTERPOLATE_PORT}/"), Value::from("http://h:8123/"));
+        assert_eq!(value("${TEST_INTERPOLATE_UNSET:-}"), Value::from(""));
+        assert_eq!(value("${TEST_INTERPOLATE_UNSET:-[a, b]}"), Value::from("[a, b]"));
+        assert_eq!(value("a ${TEST_INTERPOLATE_UNSET:-b c}"), Value::from("a b c"));
+        assert_eq!(value("$${TEST_INTERPOLATE_PORT}"), Value::from("${TEST_INTERPOLATE_PORT}"));
+        assert_eq!(value(r"\d+$"), Value::from(r"\d+$"));
+    }
+}
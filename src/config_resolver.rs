@@ -0,0 +1,457 @@
+use serde_yaml::{Mapping, Value};
+use thiserror::Error;
+
+/// Keys of a rule which is replaced by a fragment
+const REF_KEYS: [&str; 2] = ["$ref", "use"];
+/// Rules of `extends` sources are matched by these keys, the first one which is set
+const RULE_ID_KEYS: [&str; 2] = ["id", "selector"];
+/// Lists of rules merged rule by rule for `extends`
+const RULE_LIST_KEYS: [&str; 2] = ["rules", "children"];
+/// Rule of an `extends` source which is added after the rules of the base source
+const APPEND_KEY: &str = "append";
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ConfigResolveError {
+    #[error("fragment [{0}] is not found")]
+    UnknownFragment(String),
+    #[error("fragments reference each other [{0}]")]
+    FragmentCycle(String),
+    #[error("`{key}` of a rule must be a fragment name, got {value:?}")]
+    InvalidRef { key: String, value: Value },
+    #[error("fragment [{0}] is a list of rules, the rule which uses it can't have other keys")]
+    ListWithOverrides(String),
+    #[error("source [{source_name}] extends unknown source [{base}]")]
+    UnknownSource { source_name: String, base: String },
+    #[error("sources extend each other [{0}]")]
+    ExtendsCycle(String),
+    #[error("rule [{rule}] of source [{source_name}] matches no rule of the base source by `id` or `selector`, set `append: true` to add it")]
+    UnmatchedRule { source_name: String, rule: String },
+}
+
+fn ref_name(mapping: &Mapping) -> Result<Option<(&'static str, String)>, ConfigResolveError> {
+    for key in REF_KEYS {
+        match mapping.get(key) {
+            None => {}
+            Some(Value::String(name)) => return Ok(Some((key, name.clone()))),
+            Some(value) => {
+                return Err(ConfigResolveError::InvalidRef {
+                    key: key.to_owned(),
+                    value: value.clone(),
+                })
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn child_path(path: &str, key: &Value) -> String {
+    let key = key.as_str().map(str::to_owned).unwrap_or_else(|| format!("{key:?}"));
+    if path.is_empty() {
+        key
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+/// Paths of nodes of the resolved config like `sources[0].card.rules[3]` to paths of the nodes
+/// of the text they come from, None for nodes which aren't in the text as they are
+#[derive(Debug, Default)]
+pub struct ConfigOrigins(Vec<(String, Option<String>)>);
+
+impl ConfigOrigins {
+    fn add(&mut self, path: String, text_path: Option<String>) {
+        self.0.push((path, text_path));
+    }
+
+    /// Path of the text for the path of the resolved config, from its closest recorded parent
+    pub fn text_path(&self, path: &str) -> Option<String> {
+        let closest = self
+            .0
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+            })
+            .max_by_key(|(prefix, _)| prefix.len());
+        match closest {
+            None => Some(path.to_owned()),
+            Some((prefix, text_path)) => text_path.as_ref().map(|text_path| format!("{text_path}{}", &path[prefix.len()..])),
+        }
+    }
+}
+
+struct FragmentResolver<'f> {
+    fragments: &'f Mapping,
+    /// names of fragments being resolved, for cycles
+    stack: Vec<String>,
+    origins: ConfigOrigins,
+}
+
+impl FragmentResolver<'_> {
+    fn fragment(&mut self, name: &str) -> Result<Value, ConfigResolveError> {
+        if self.stack.iter().any(|open| open == name) {
+            let mut cycle = self.stack.clone();
+            cycle.push(name.to_owned());
+            return Err(ConfigResolveError::FragmentCycle(cycle.join(" -> ")));
+        }
+        let fragment = self
+            .fragments
+            .get(name)
+            .ok_or_else(|| ConfigResolveError::UnknownFragment(name.to_owned()))?
+            .clone();
+        self.stack.push(name.to_owned());
+        // nodes of fragments are reported at the rule which uses the fragment
+        let fragment = self.resolve(fragment, "", None);
+        self.stack.pop();
+        fragment
+    }
+
+    /// Rule mapping with its fragment, keys of the rule win
+    fn resolve_mapping(&mut self, mut mapping: Mapping, path: &str, text_path: Option<&str>) -> Result<Value, ConfigResolveError> {
+        let Some((key, name)) = ref_name(&mapping)? else {
+            let mut resolved = Mapping::new();
+            for (key, value) in mapping {
+                let value = self.resolve(value, &child_path(path, &key), text_path.map(|text_path| child_path(text_path, &key)).as_deref())?;
+                resolved.insert(key, value);
+            }
+            return Ok(Value::Mapping(resolved));
+        };
+        mapping.remove(key);
+        match self.fragment(&name)? {
+            Value::Mapping(mut fragment) => {
+                for (key, value) in mapping {
+                    let value = self.resolve(value, &child_path(path, &key), text_path.map(|text_path| child_path(text_path, &key)).as_deref())?;
+                    fragment.insert(key, value);
+                }
+                Ok(Value::Mapping(fragment))
+            }
+            _ if !mapping.is_empty() => Err(ConfigResolveError::ListWithOverrides(name)),
+            fragment => Ok(fragment),
+        }
+    }
+
+    /// Every reference replaced, fragments which are lists are spliced into lists,
+    /// `path` is the path of the value in the resolved config, `text_path` in the text
+    fn resolve(&mut self, value: Value, path: &str, text_path: Option<&str>) -> Result<Value, ConfigResolveError> {
+        match value {
+            Value::Mapping(mapping) => self.resolve_mapping(mapping, path, text_path),
+            Value::Sequence(items) => {
+                let mut resolved = Vec::with_capacity(items.len());
+                for (idx, item) in items.into_iter().enumerate() {
+                    let is_ref = match &item {
+                        Value::Mapping(mapping) => ref_name(mapping)?.is_some(),
+                        _ => false,
+                    };
+                    let item_text_path = text_path.map(|text_path| format!("{text_path}[{idx}]"));
+                    let item_path = format!("{path}[{}]", resolved.len());
+                    match self.resolve(item, &item_path, item_text_path.as_deref())? {
+                        Value::Sequence(fragment) if is_ref => {
+                            for item in fragment {
+                                if let Some(item_text_path) = &item_text_path {
+                                    self.origins.add(format!("{path}[{}]", resolved.len()), Some(item_text_path.clone()));
+                                }
+                                resolved.push(item);
+                            }
+                        }
+                        item => {
+                            if let Some(item_text_path) = item_text_path {
+                                self.origins.add(item_path, Some(item_text_path));
+                            }
+                            resolved.push(item);
+                        }
+                    }
+                }
+                Ok(Value::Sequence(resolved))
+            }
+            Value::Tagged(tagged) => Ok(Value::Tagged(Box::new(serde_yaml::value::TaggedValue {
+                tag: tagged.tag,
+                value: self.resolve(tagged.value, path, text_path)?,
+            }))),
+            value => Ok(value),
+        }
+    }
+}
+
+fn rule_id(rule: &Value) -> Option<(&'static str, &Value)> {
+    RULE_ID_KEYS.iter().find_map(|key| {
+        rule.get(key)
+            .filter(|id| !matches!(id, Value::Null) && id.as_str() != Some(""))
+            .map(|id| (*key, id))
+    })
+}
+
+/// Mappings are merged key by key, `rules` and `children` lists rule by rule matched by `id`
+/// or `selector`, other values of `over` replace ones of `base`
+fn merge(base: Value, over: Value, key: Option<&str>, source_name: &str) -> Result<Value, ConfigResolveError> {
+    match (base, over) {
+        (Value::Mapping(mut base), Value::Mapping(over)) => {
+            for (over_key, over_value) in over {
+                let value = match base.remove(&over_key) {
+                    Some(base_value) => merge(base_value, over_value, over_key.as_str(), source_name)?,
+                    None => over_value,
+                };
+                base.insert(over_key, value);
+            }
+            Ok(Value::Mapping(base))
+        }
+        (Value::Sequence(mut base), Value::Sequence(over)) if key.is_some_and(|key| RULE_LIST_KEYS.contains(&key)) => {
+            for mut rule in over {
+                let append = match &mut rule {
+                    Value::Mapping(rule) => rule.remove(APPEND_KEY).and_then(|append| append.as_bool()).unwrap_or_default(),
+                    _ => false,
+                };
+                if append {
+                    base.push(rule);
+                    continue;
+                }
+                let id = rule_id(&rule);
+                let found = id.and_then(|id| base.iter().position(|base_rule| rule_id(base_rule) == Some(id)));
+                let Some(idx) = found else {
+                    return Err(ConfigResolveError::UnmatchedRule {
+                        source_name: source_name.to_owned(),
+                        rule: id.and_then(|(_, id)| id.as_str()).unwrap_or_default().to_owned(),
+                    });
+                };
+                base[idx] = merge(base[idx].clone(), rule, None, source_name)?;
+            }
+            Ok(Value::Sequence(base))
+        }
+        (_, over) => Ok(over),
+    }
+}
+
+fn source_name(source: &Value) -> String {
+    source.get("name").and_then(Value::as_str).unwrap_or_default().to_owned()
+}
+
+/// Source with every source it extends merged in, `chain` is the path of names for cycles
+fn extended_source(sources: &[Value], idx: usize, chain: &mut Vec<String>) -> Result<Value, ConfigResolveError> {
+    let source = &sources[idx];
+    let name = source_name(source);
+    let Some(base) = source.get("extends").and_then(Value::as_str) else {
+        return Ok(source.clone());
+    };
+    chain.push(name.clone());
+    if chain.iter().any(|open| open == base) {
+        chain.push(base.to_owned());
+        return Err(ConfigResolveError::ExtendsCycle(chain.join(" -> ")));
+    }
+    let base_idx = sources
+        .iter()
+        .position(|other| source_name(other) == base)
+        .ok_or_else(|| ConfigResolveError::UnknownSource {
+            source_name: name.clone(),
+            base: base.to_owned(),
+        })?;
+    let base_source = extended_source(sources, base_idx, chain)?;
+    chain.pop();
+    merge(base_source, source.clone(), None, &name)
+}
+
+/// Config document with `$ref` and `use` of `fragments` replaced and `extends` sources merged
+pub fn resolve_config(config: Value) -> Result<Value, ConfigResolveError> {
+    resolve_config_with_origins(config).map(|(resolved, _)| resolved)
+}
+
+/// `resolve_config` with the paths of the text for lines of errors, nodes of sources which
+/// extend other sources have none
+pub fn resolve_config_with_origins(config: Value) -> Result<(Value, ConfigOrigins), ConfigResolveError> {
+    let Value::Mapping(config) = config else {
+        return Ok((config, ConfigOrigins::default()));
+    };
+    let fragments = match config.get("fragments") {
+        Some(Value::Mapping(fragments)) => fragments.clone(),
+        _ => Mapping::new(),
+    };
+    let mut resolver = FragmentResolver {
+        fragments: &fragments,
+        stack: Vec::new(),
+        origins: ConfigOrigins::default(),
+    };
+    let mut resolved = Mapping::new();
+    for (key, value) in config {
+        let value = if key.as_str() == Some("fragments") {
+            value
+        } else {
+            let path = child_path("", &key);
+            resolver.resolve(value, &path, Some(&path))?
+        };
+        resolved.insert(key, value);
+    }
+    let mut origins = resolver.origins;
+
+    if let Some(Value::Sequence(sources)) = resolved.get("sources") {
+        let sources = (0..sources.len())
+            .map(|idx| extended_source(sources, idx, &mut Vec::new()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (idx, source) in sources.iter().enumerate() {
+            if source.get("extends").is_some() {
+                let path = format!("sources[{idx}]");
+                origins.0.retain(|(prefix, _)| {
+                    !prefix
+                        .strip_prefix(path.as_str())
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+                });
+                origins.add(path, None);
+            }
+        }
+        resolved.insert("sources".into(), Value::Sequence(sources));
+    }
+    Ok((Value::Mapping(resolved), origins))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(text: &str) -> Result<Value, ConfigResolveError> {
+        resolve_config(serde_yaml::from_str(text).unwrap())
+    }
+
+    #[test]
+    fn test_resolve_config() {
+        let config = resolve(
+            r#"
+fragments:
+  address:
+    mapping: Address
+    regex: '(?P<city>\w+)$'
+    captures: { city: City }
+  geo:
+    - { selector: '$.lat', selector_type: jsonpath, mapping: Lat }
+    - $ref: lng
+  lng: { selector: '$.lng', selector_type: jsonpath, mapping: Lng }
+sources:
+  - name: base
+    root_url: 'https://base.com'
+    card:
+      rules:
+        - { id: title, selector: h1, mapping: Title }
+        - { selector: p.address, use: address }
+        - selector: script
+          parse_as: json
+          children: [{ $ref: geo }, { selector: '$.id', selector_type: jsonpath, mapping: ID }]
+        - selector: 'div[aria-label="Property header"]'
+          children:
+            - { selector: span.beds, mapping: Beds }
+            - { selector: span.baths, mapping: Baths }
+  - name: regional
+    extends: base
+    root_url: 'https://base.ae'
+    card:
+      rules:
+        - { id: title, selector: h2 }
+        - { selector: span.price, mapping: Price, append: true }
+        - selector: 'div[aria-label="Property header"]'
+          children:
+            - { selector: span.baths, mapping: Bathrooms }
+"#,
+        )
+        .unwrap();
+        let expected: Value = serde_yaml::from_str(
+            r#"
+- selector: script
+  parse_as: json
+  children:
+    - { selector: '$.lat', selector_type: jsonpath, mapping: Lat }
+    - { selector: '$.lng', selector_type: jsonpath, mapping: Lng }
+    - { selector: '$.id', selector_type: jsonpath, mapping: ID }
+"#,
+        )
+        .unwrap();
+        let sources = &config["sources"];
+        assert_eq!(sources[0]["card"]["rules"][2], expected[0]);
+        assert_eq!(
+            sources[0]["card"]["rules"][1],
+            serde_yaml::from_str::<Value>(r#"{ mapping: Address, regex: '(?P<city>\w+)$', captures: { city: City }, selector: p.address }"#)
+                .unwrap()
+        );
+        let regional = &sources[1];
+        let regional_rules = &regional["card"]["rules"];
+        assert_eq!(regional["root_url"], Value::from("https://base.ae"));
+        assert_eq!(regional_rules[0], serde_yaml::from_str::<Value>("{ id: title, selector: h2, mapping: Title }").unwrap());
+        assert_eq!(regional_rules[1], sources[0]["card"]["rules"][1]);
+        assert_eq!(regional_rules[3]["children"][0], sources[0]["card"]["rules"][3]["children"][0]);
+        assert_eq!(regional_rules[3]["children"][1]["mapping"], Value::from("Bathrooms"));
+        assert_eq!(regional_rules[3]["children"].as_sequence().unwrap().len(), 2);
+        assert_eq!(regional_rules[4], serde_yaml::from_str::<Value>("{ selector: span.price, mapping: Price }").unwrap());
+        assert_eq!(regional_rules.as_sequence().unwrap().len(), 5);
+
+        assert_eq!(
+            resolve("sources: [{ name: a, card: { rules: [{ selector: h1 }] } }, { name: b, extends: a, card: { rules: [{ selector: h2 }] } }]"),
+            Err(ConfigResolveError::UnmatchedRule {
+                source_name: "b".into(),
+                rule: "h2".into()
+            })
+        );
+        assert_eq!(
+            resolve("fragments: { a: { use: b }, b: [{ $ref: a }] }\nrules: [{ use: a }]"),
+            Err(ConfigResolveError::FragmentCycle("a -> b -> a".into()))
+        );
+        assert_eq!(
+            resolve("rules: [{ use: missing }]"),
+            Err(ConfigResolveError::UnknownFragment("missing".into()))
+        );
+        assert_eq!(
+            resolve("fragments: { a: [] }\nrules: [{ use: a, mapping: X }]"),
+            Err(ConfigResolveError::ListWithOverrides("a".into()))
+        );
+        assert!(matches!(resolve("rules: [{ $ref: [a] }]"), Err(ConfigResolveError::InvalidRef { .. })));
+        assert_eq!(
+            resolve("sources: [{ name: a, extends: b }, { name: b, extends: a }]"),
+            Err(ConfigResolveError::ExtendsCycle("a -> b -> a".into()))
+        );
+        assert_eq!(
+            resolve("sources: [{ name: a, extends: c }]"),
+            Err(ConfigResolveError::UnknownSource {
+                source_name: "a".into(),
+                base: "c".into()
+            })
+        );
+    }
+
+    #[test]
+    fn test_shipped_address_captures() {
+        use crate::{
+            page_walker::PageWalker,
+            transform_html::{compiled_rule::compile_rules, defs::TransformSettings, transform_html_map},
+        };
+
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("etl-config.yaml");
+        let config = PageWalker::parse_config(&path).unwrap();
+        let header = &config.sources[0].card.rules[0].children[0];
+        let rules = compile_rules(std::slice::from_ref(header)).unwrap();
+        let address = |text: &str| {
+            let html = format!(r#"<div aria-label="Property header">{text}</div>"#);
+            let data = transform_html_map(&html, &rules, &TransformSettings::default()).unwrap();
+            let field = |name: &str| data.get(name).map(|value| value.exract_value().clone());
+            (field("District"), field("City"))
+        };
+        assert_eq!(address("Marina Gate 1, Dubai Marina, Dubai"), (Some("Dubai Marina".into()), Some("Dubai".into())));
+        assert_eq!(address("Dubai Marina, Dubai"), (Some("Dubai Marina".into()), Some("Dubai".into())));
+        assert_eq!(address("Al Barsha South, Al Barsha, Dubai").1, Some("Dubai".into()));
+        assert_eq!(address("Dubai").1, Some("Dubai".into()));
+    }
+
+    #[test]
+    fn test_shipped_geo_coordinates() {
+        use crate::{
+            page_walker::PageWalker,
+            transform_html::{compiled_rule::compile_rules, defs::TransformSettings, transform_html_map},
+        };
+
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("etl-config.yaml");
+        let config = PageWalker::parse_config(&path).unwrap();
+        let overview = &config.sources[0].card.rules[1];
+        let html = r#"
+        <div aria-label="Property overview">
+          <div class="_96aa05ec"><span>Villa</span><script>window.state = {};</script></div>
+          <script type="application/ld+json">{"@type": "BreadcrumbList"}</script>
+          <script type="application/ld+json">{"geo": {"@type": "GeoCoordinates", "latitude": 25.07, "longitude": 55.13}}</script>
+        </div>"#;
+        let data = transform_html_map(html, &compile_rules(std::slice::from_ref(overview)).unwrap(), &TransformSettings::default())
+            .unwrap();
+        assert_eq!(data["Coords Lat"].exract_value(), "25.07");
+        assert_eq!(data["Coords Lng"].exract_value(), "55.13");
+    }
+}
@@ -0,0 +1,469 @@
+use std::{collections::HashMap, fmt, fs, path::Path, str::FromStr};
+
+use reqwest::header::{HeaderName, HeaderValue};
+use yaml_rust2::{
+    parser::{MarkedEventReceiver, Parser},
+    scanner::Marker,
+    Event,
+};
+
+use crate::{
+    config_loader::{load_config, load_config_value},
+    config_resolver::{resolve_config, resolve_config_with_origins, ConfigOrigins},
+    etl_config_parser::EtlConfig,
+    page_walker::PageWalkerError,
+    transform_html::{compiled_rule::CompiledRule, defs::*},
+};
+
+/// One problem of an etl config
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigIssue {
+    pub source_name: Option<String>,
+    /// path inside of the source like `card.rules[3].children[1]`, from the root outside of sources
+    pub path: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: ")?,
+            None => write!(f, "line ?: ")?,
+        }
+        if let Some(source_name) = &self.source_name {
+            write!(f, "source [{source_name}] ")?;
+        }
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+enum Frame {
+    Map { path: String, key: Option<String> },
+    Seq { path: String, index: usize },
+}
+
+/// Line of every YAML node by its path like `sources[1].card.rules[3]`
+#[derive(Default)]
+pub(crate) struct LineIndex {
+    lines: HashMap<String, usize>,
+    frames: Vec<Frame>,
+}
+
+impl LineIndex {
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut index = LineIndex::default();
+        // a broken document keeps lines read so far, serde reports the error itself
+        let _ = Parser::new_from_str(text).load(&mut index, false);
+        index
+    }
+
+    /// Path of the node starting at the mark, keys of a map are recorded here as well
+    fn node_path(&mut self, mark: Marker) -> String {
+        match self.frames.last() {
+            None => String::new(),
+            Some(Frame::Map { path, key }) => {
+                let key = key.clone().unwrap_or_default();
+                if path.is_empty() {
+                    key
+                } else {
+                    format!("{path}.{key}")
+                }
+            }
+            Some(Frame::Seq { path, index }) => {
+                let path = format!("{path}[{index}]");
+                self.lines.insert(path.clone(), mark.line());
+                path
+            }
+        }
+    }
+
+    fn node_finished(&mut self) {
+        match self.frames.last_mut() {
+            Some(Frame::Map { key, .. }) => *key = None,
+            Some(Frame::Seq { index, .. }) => *index += 1,
+            None => {}
+        }
+    }
+
+    /// Line of the path or of its closest parent
+    fn line(&self, path: &str) -> Option<usize> {
+        let mut path = path;
+        loop {
+            if let Some(line) = self.lines.get(path) {
+                return Some(*line);
+            }
+            path = &path[..path.rfind(['.', '['])?];
+        }
+    }
+
+    /// Deepest path starting at the line or at the closest line above it
+    pub(crate) fn path_at(&self, line: usize) -> Option<String> {
+        let start = self.lines.values().filter(|path_line| **path_line <= line).max()?;
+        self.lines
+            .iter()
+            .filter(|(_, path_line)| *path_line == start)
+            .map(|(path, _)| path)
+            .max_by_key(|path| (path.matches(['.', '[']).count(), path.len()))
+            .cloned()
+    }
+}
+
+impl MarkedEventReceiver for LineIndex {
+    fn on_event(&mut self, ev: Event, mark: Marker) {
+        match ev {
+            Event::Scalar(value, ..) => {
+                if let Some(Frame::Map { path, key: key @ None }) = self.frames.last_mut() {
+                    let key_path = if path.is_empty() {
+                        value.clone()
+                    } else {
+                        format!("{path}.{value}")
+                    };
+                    self.lines.insert(key_path, mark.line());
+                    *key = Some(value);
+                    return;
+                }
+                self.node_path(mark);
+                self.node_finished();
+            }
+            Event::Alias(_) => {
+                self.node_path(mark);
+                self.node_finished();
+            }
+            Event::MappingStart(..) => {
+                let path = self.node_path(mark);
+                self.frames.push(Frame::Map { path, key: None });
+            }
+            Event::SequenceStart(..) => {
+                let path = self.node_path(mark);
+                self.frames.push(Frame::Seq { path, index: 0 });
+            }
+            Event::MappingEnd | Event::SequenceEnd => {
+                self.frames.pop();
+                self.node_finished();
+            }
+            _ => {}
+        }
+    }
+}
+
+struct Validator {
+    index: LineIndex,
+    origins: ConfigOrigins,
+    issues: Vec<ConfigIssue>,
+}
+
+impl Validator {
+    /// `field` is the most precise YAML node for the line, `path` is reported
+    fn report(&mut self, source: Option<(usize, &str)>, path: &str, field: &str, message: String) {
+        let full_path = match source {
+            Some((idx, _)) => format!("sources[{idx}].{path}{field}"),
+            None => format!("{path}{field}"),
+        };
+        self.issues.push(ConfigIssue {
+            source_name: source.map(|(_, name)| name.to_owned()),
+            path: path.to_owned(),
+            line: self.origins.text_path(&full_path).and_then(|text_path| self.index.line(&text_path)),
+            message,
+        });
+    }
+
+    /// `in_json` is set below a `parse_as: json` rule
+    fn check_rules(&mut self, source: (usize, &str), prefix: &str, rules: &[ParserTransfromRule], in_json: bool) {
+        for (idx, rule) in rules.iter().enumerate() {
+            let path = format!("{prefix}[{idx}]");
+            if let Err(err) = CompiledRule::compile_node(rule, in_json) {
+                self.report(Some(source), &path, &error_field(rule, &err), err.to_string());
+            }
+            let children_in_json = in_json || rule.parse_as == ParseAs::Json;
+            self.check_rules(source, &format!("{path}.children"), &rule.children, children_in_json);
+        }
+    }
+}
+
+/// Field of the rule the error is about, for the line
+fn error_field(rule: &ParserTransfromRule, err: &TransformError) -> String {
+    match err {
+        TransformError::SelectorError { selector, .. } if *selector != rule.selector => rule
+            .selectors
+            .iter()
+            .position(|fallback| fallback == selector)
+            .map(|idx| format!(".selectors[{idx}]"))
+            .unwrap_or_default(),
+        TransformError::SelectorError { .. } => ".selector".into(),
+        TransformError::RegexError { regex, .. } if *regex == rule.regex => ".regex".into(),
+        TransformError::RegexError { regex, .. } if rule.regex_sub_value.first() == Some(regex) || rule.regex_sub_value.len() != 2 => {
+            ".regex_sub_value".into()
+        }
+        TransformError::RegexError { .. } | TransformError::FilterError { .. } => ".filters".into(),
+        TransformError::DateFormatError { .. } => ".date_format".into(),
+        _ => String::new(),
+    }
+}
+
+fn document_issue(line: Option<usize>, message: String) -> Vec<ConfigIssue> {
+    vec![ConfigIssue {
+        source_name: None,
+        path: String::new(),
+        line,
+        message,
+    }]
+}
+
+fn serde_issue(err: serde_yaml::Error) -> Vec<ConfigIssue> {
+    document_issue(err.location().map(|location| location.line()), err.to_string())
+}
+
+/// Every problem of the config text ordered by line, a config which can't be deserialized
+/// has one issue with the serde error
+pub fn validate_config_str(text: &str) -> Vec<ConfigIssue> {
+    let config: serde_yaml::Value = match serde_yaml::from_str(text) {
+        Ok(config) => config,
+        Err(err) => return serde_issue(err),
+    };
+    let (resolved, origins) = match resolve_config_with_origins(config.clone()) {
+        Ok(resolved) => resolved,
+        Err(err) => return document_issue(None, err.to_string()),
+    };
+    // the text keeps lines of serde errors while nothing is resolved
+    let etl_config: Result<EtlConfig, _> = if resolved == config {
+        serde_yaml::from_str(text)
+    } else {
+        serde_yaml::from_value(resolved)
+    };
+    match etl_config {
+        Ok(etl_config) => validate_etl_config(LineIndex::parse(text), origins, &etl_config),
+        Err(err) => serde_issue(err),
+    }
+}
+
+/// `origins` map paths of the resolved config to paths of the text of `index`
+fn validate_etl_config(index: LineIndex, origins: ConfigOrigins, etl_config: &EtlConfig) -> Vec<ConfigIssue> {
+    let mut validator = Validator {
+        index,
+        origins,
+        issues: Vec::new(),
+    };
+    for (name, value) in etl_config.http.headers.iter() {
+        if let Err(err) = HeaderName::from_str(name) {
+            validator.report(None, &format!("http.headers.{name}"), "", format!("header name: {err}"));
+        }
+        if let Err(err) = HeaderValue::from_str(value) {
+            validator.report(None, &format!("http.headers.{name}"), "", format!("header value: {err}"));
+        }
+    }
+    for (idx, source) in etl_config.sources.iter().enumerate() {
+        let source_ref = (idx, source.name.as_str());
+        validator.check_rules(source_ref, "menu.rules", &source.menu.rules, false);
+        validator.check_rules(source_ref, "card.rules", &source.card.rules, false);
+        if let Some(partitioning) = &source.partitioning {
+            if partitioning.min_value > partitioning.max_value {
+                let message = format!("min_value [{}] is over max_value [{}]", partitioning.min_value, partitioning.max_value);
+                validator.report(Some(source_ref), "partitioning", ".min_value", message);
+            }
+            if partitioning.min_width == 0 {
+                validator.report(Some(source_ref), "partitioning", ".min_width", "min_width must be at least 1".into());
+            }
+        }
+    }
+    if etl_config.clickhouse.as_ref().is_some_and(|clickhouse| clickhouse.drop_db) {
+        let message = "drop_db isn't supported, drop_table drops the loading table".into();
+        validator.report(None, "clickhouse", ".drop_db", message);
+    }
+
+    let mut issues = validator.issues;
+    issues.sort_by_key(|issue| issue.line);
+    issues
+}
+
+/// Lines are known for a YAML file without includes and environment variables,
+/// a config which can't be loaded has one issue naming the file and the key
+pub fn validate_config(etl_config_path: &Path) -> Result<Vec<ConfigIssue>, PageWalkerError> {
+    let loaded = match load_config_value(etl_config_path) {
+        Ok(loaded) => loaded,
+        Err(err) => return Ok(document_issue(None, err.to_string())),
+    };
+    let is_yaml = !matches!(
+        etl_config_path.extension().and_then(|extension| extension.to_str()),
+        Some("json" | "toml")
+    );
+    let text = fs::read_to_string(etl_config_path)?;
+    let is_plain = is_yaml
+        && serde_yaml::from_str(&text)
+            .ok()
+            .and_then(|config| resolve_config(config).ok())
+            .is_some_and(|resolved| resolved == loaded);
+    if is_plain {
+        return Ok(validate_config_str(&text));
+    }
+    Ok(match load_config(etl_config_path) {
+        Ok(etl_config) => validate_etl_config(LineIndex::default(), ConfigOrigins::default(), &etl_config),
+        Err(err) => document_issue(None, err.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn test_validate_config() {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("etl-config.yaml");
+        assert_eq!(validate_config(&path).unwrap(), vec![]);
+
+        let text = r#"
+http:
+  retries:
+    max_retries: 1
+    backoff_factor: 2
+    status_forcelist: []
+    timeout: 5
+  headers:
+    user-agent: 'test'
+    bad header: 'x'
+sources:
+  - name: stub
+    root_url: 'http://localhost'
+    menu:
+      page_limit: 1
+      default_url: /
+      page_url_sub: '?page=\1'
+      first_page_url: ''
+      rules:
+        - selector: a[[href]
+          mapping: url
+    card:
+      rules:
+        - selector: h1
+        - selector: div
+          children:
+            - mapping: Price
+              regex_sub_value: ['[^\d+]']
+            - selector: span:-soup-contains("x") >
+              mapping: Title
+            - selector: span
+              mapping: Title
+              regex_sub_value: ['(unclosed', '\1']
+            - selector: time
+              mapping: Listed
+              type: date
+              date_format: '%Y-%Q'
+            - selector: a
+              mapping: Link
+              filters:
+                - trim
+                - url_join: 'not a url'
+            - selector: '$.geo'
+              selector_type: jsonpath
+              mapping: Geo
+            - mapping: Address
+              regex: '(?P<city>\w+)$'
+              captures: { city: City, district: District }
+            - selector: h2
+              selectors: ['h3', 'h4[']
+              mapping: Subtitle
+            - selector: img
+              mapping: Image
+              attribute_name: src
+              extract: attributes
+            - selector: li
+              pick: unique
+              children:
+                - mapping: Item
+        - selector: script
+          parse_as: json
+          children:
+            - selector: '$.inner'
+              selector_type: jsonpath
+              parse_as: json
+"#;
+        let issues: Vec<(Option<String>, String, Option<usize>)> = validate_config_str(text)
+            .into_iter()
+            .map(|issue| (issue.source_name, issue.path, issue.line))
+            .collect();
+        let stub = || Some("stub".to_string());
+        assert_eq!(
+            issues,
+            vec![
+                (None, "http.headers.bad header".into(), Some(10)),
+                (stub(), "menu.rules[0]".into(), Some(20)),
+                (stub(), "card.rules[1].children[0]".into(), Some(28)),
+                (stub(), "card.rules[1].children[1]".into(), Some(29)),
+                (stub(), "card.rules[1].children[2]".into(), Some(33)),
+                (stub(), "card.rules[1].children[3]".into(), Some(37)),
+                (stub(), "card.rules[1].children[4]".into(), Some(40)),
+                (stub(), "card.rules[1].children[5]".into(), Some(43)),
+                (stub(), "card.rules[1].children[6]".into(), Some(47)),
+                (stub(), "card.rules[1].children[7]".into(), Some(50)),
+                (stub(), "card.rules[1].children[8]".into(), Some(52)),
+                (stub(), "card.rules[1].children[9]".into(), Some(56)),
+                (stub(), "card.rules[2].children[0]".into(), Some(63)),
+            ]
+        );
+
+        let issues = validate_config_str("http: [1, 2]\nsources: [{ name: a, extends: b }]\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].message, "source [a] extends unknown source [b]");
+
+        let text = r#"
+fragments:
+  heading:
+    - { selector: h1, mapping: Title }
+    - { selector: 'h2[', mapping: Subtitle }
+http:
+  retries: { max_retries: 1, backoff_factor: 2, status_forcelist: [], timeout: 5 }
+  headers: {}
+sources:
+  - name: stub
+    root_url: 'http://localhost'
+    menu: { page_limit: 1, default_url: /, page_url_sub: '', first_page_url: '', rules: [] }
+    partitioning: { lower_param: pf, upper_param: pt, min_value: 10, max_value: 0, min_width: 0 }
+    card:
+      rules:
+        - $ref: heading
+        - selector: p
+          mapping: Text
+          date_format: '%Q'
+          type: date
+clickhouse:
+  connection_name: db
+  drop_db: true
+  database: { name: default }
+  loading_table: { name: adverts, columns: [] }
+  filter_table: { name: filters, columns: [] }
+"#;
+        let issues: Vec<(String, Option<usize>)> =
+            validate_config_str(text).into_iter().map(|issue| (issue.path, issue.line)).collect();
+        assert_eq!(
+            issues,
+            vec![
+                ("partitioning".into(), Some(13)),
+                ("partitioning".into(), Some(13)),
+                ("card.rules[1]".into(), Some(16)),
+                ("card.rules[2]".into(), Some(19)),
+                ("clickhouse".into(), Some(23)),
+            ]
+        );
+
+        let issues = validate_config_str("http: [1, 2]\nsources: []\n");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(1));
+        assert!(issues[0].to_string().starts_with("line 1: http: invalid type"));
+
+        let path = std::env::temp_dir().join(format!("test_validate_config_{}.json", std::process::id()));
+        let http = r#"{ "retries": { "max_retries": 1, "backoff_factor": 2, "status_forcelist": [], "timeout": 5 }, "headers": { "bad header": "x" } }"#;
+        fs::write(&path, format!(r#"{{ "http": {http}, "sources": [] }}"#)).unwrap();
+        let issues = validate_config(&path).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!((issues[0].path.as_str(), issues[0].line), ("http.headers.bad header", None));
+        fs::write(&path, r#"{ "http": [] }"#).unwrap();
+        let issues = validate_config(&path).unwrap();
+        assert!(issues[0].message.contains("http: invalid type"), "{}", issues[0].message);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
 
-use crate::transform_html::defs::ParserTransfromRule;
+use crate::transform_html::{defs::ParserTransfromRule, text_normalization::TextNormalization};
 
 
 
@@ -9,16 +9,70 @@ use crate::transform_html::defs::ParserTransfromRule;
 pub struct SourceConfig {
 	pub name:       String,
 	pub root_url:   String,
+	/// name of the source this one is merged over, resolved by `resolve_config`, rules override
+	/// the base rule with the same `id` or `selector`, rules with `append: true` are added
+	#[serde(default)]
+	pub extends:    Option<String>,
 	
     pub menu:       MenuRules,
     pub card:       CardRules,
+
+    #[serde(default)]
+    pub concurrency: ConcurrencyConfig,
+    /// overrides `http.rate_limit` for the source
+    #[serde(default)]
+    pub rate_limit: Option<HttpConfigRateLimit>,
+    /// fetch robots.txt of every host, skip disallowed urls and honor `Crawl-delay`
+    #[serde(default)]
+    pub respect_robots_txt: bool,
+    #[serde(default)]
+    pub partitioning: Option<PartitioningConfig>,
+    /// cleaning of extracted text, rules override it with their `normalize`
+    #[serde(default)]
+    pub normalize: TextNormalization,
+}
+
+
+/// Numeric query parameter range of a filter, split in halves while the filter
+/// has more cards than `menu.page_limit` pages can show
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PartitioningConfig {
+    pub lower_param:    String,
+    pub upper_param:    String,
+    /// bounds of a filter url without the params
+    pub min_value:      u64,
+    pub max_value:      u64,
+    /// ranges of this many values are crawled as is
+    #[serde(default = "PartitioningConfig::default_min_width")]
+    pub min_width:      u64,
+}
+
+impl PartitioningConfig {
+    fn default_min_width() -> u64 {
+        1
+    }
+}
+
+
+/// Amount of menu pages and of card pages per menu page crawled at once
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ConcurrencyConfig {
+    pub menu_pages: usize,
+    pub cards:      usize,
+}
+
+impl Default for ConcurrencyConfig {
+    fn default() -> Self {
+        Self { menu_pages: 1, cards: 1 }
+    }
 }
 
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MenuRules {
     pub page_limit:     i32,
-    #[deprecated(note="unused in this implementation, amount of pages comes from cli")]
+    /// cards of a full menu page for the all pages mode, zero takes the count of the first page
     #[serde(default)]
     pub cards_per_page: i32,
     pub default_url:    String,
@@ -44,22 +98,122 @@ pub struct HttpConfigRetries {
 }
 
 
+/// Per host pacing
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HttpConfigRateLimit {
+	#[serde(deserialize_with = "HttpConfigRateLimit::positive_rate")]
+	pub requests_per_second:    f64,
+	#[serde(default = "HttpConfigRateLimit::default_burst")]
+	pub burst:                  u32,
+	#[serde(default)]
+	pub min_delay_ms:           u64,
+}
+
+impl HttpConfigRateLimit {
+	fn default_burst() -> u32 {
+		1
+	}
+
+	fn positive_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+		let rate = f64::deserialize(deserializer)?;
+		if rate.is_finite() && rate > 0.0 {
+			Ok(rate)
+		} else {
+			Err(D::Error::custom(format!("requests_per_second must be a positive number, got [{rate}]")))
+		}
+	}
+}
+
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct HttpConfig {
 	pub retries: HttpConfigRetries,
 	pub headers: HashMap<String, String>,	
+	#[serde(default)]
+	pub rate_limit: Option<HttpConfigRateLimit>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClickhouseDatabase {
+	pub name: String,
+}
+
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClickhouseTable {
+	pub name:           String,
+	#[serde(default)]
+	pub description:    String,
+	pub columns:        Vec<String>,
+}
+
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreparedFilter {
+	pub url:            String,
+	/// empty name matches any source
+	#[serde(default)]
+	pub source_name:    String,
+	/// page range overrides the cli one
+	#[serde(default)]
+	pub begin_page:     Option<usize>,
+	#[serde(default)]
+	pub end_page:       Option<usize>,
+}
+
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClickhouseFilterTable {
+	pub name:               String,
+	#[serde(default)]
+	pub description:        String,
+	pub columns:            Vec<String>,
+	#[serde(default)]
+	pub prepared_records:   Vec<PreparedFilter>,
+}
+
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ClickhouseConfig {
+	pub connection_name:    String,
+	/// not supported, reported by `validate` and the sink, see `drop_table`
+	#[serde(default)]
+	pub drop_db:            bool,
+	/// drop the loading table before creating it, every loaded row is lost
+	#[serde(default)]
+	pub drop_table:         bool,
+	pub database:           ClickhouseDatabase,
+	pub loading_table:      ClickhouseTable,
+	pub filter_table:       ClickhouseFilterTable,
+}
+
+/// Split of a source crawl into tasks for an external orchestrator,
+/// `default` source name applies to sources without own entry
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DagConfig {
+	pub source_name:            String,
+	pub menu_pages_per_task:    usize,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct EtlConfig {
+	/// rules referenced by `$ref` or `use` of rules, resolved by `resolve_config`
+	#[serde(default)]
+	pub fragments:  HashMap<String, serde_yaml::Value>,
+	#[serde(default)]
+	pub dag:        Vec<DagConfig>,
 	pub http:       HttpConfig,
 	pub sources:    Vec<SourceConfig>,
+	#[serde(default)]
+	pub clickhouse: Option<ClickhouseConfig>,
 }
 
 
 
 #[cfg(test)]
 mod tests {
+    use std::{fs::File, io::BufReader, path::PathBuf};
+
     use super::*;
 
     #[test]
@@ -67,4 +221,93 @@ mod tests {
         
     }
 
+    fn shipped_config() -> Result<EtlConfig, anyhow::Error> {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("etl-config.yaml");
+        Ok(serde_yaml::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    #[test]
+    fn test_rate_limit() -> Result<(), anyhow::Error> {
+        let etl_config = shipped_config()?;
+        assert_eq!(
+            etl_config.http.rate_limit,
+            Some(HttpConfigRateLimit {
+                requests_per_second: 2.0,
+                burst: 4,
+                min_delay_ms: 100,
+            })
+        );
+        assert_eq!(etl_config.sources[0].rate_limit.as_ref().unwrap().burst, 1);
+        assert_eq!(etl_config.sources[1].rate_limit, None);
+        for rate in ["0", "-1", ".inf", ".nan"] {
+            let err = serde_yaml::from_str::<HttpConfigRateLimit>(&format!("requests_per_second: {rate}")).unwrap_err();
+            assert!(err.to_string().contains("must be a positive number"), "{err}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_respect_robots_txt() -> Result<(), anyhow::Error> {
+        let etl_config = shipped_config()?;
+        assert!(etl_config.sources.iter().all(|s| s.respect_robots_txt));
+        let source: SourceConfig = serde_yaml::from_str(
+            "{ name: a, root_url: '', menu: { page_limit: 1, default_url: /, page_url_sub: '', first_page_url: '', rules: [] }, card: { rules: [] } }",
+        )?;
+        assert!(!source.respect_robots_txt);
+        Ok(())
+    }
+
+    #[test]
+    fn test_partitioning() -> Result<(), anyhow::Error> {
+        let etl_config = shipped_config()?;
+        assert_eq!(
+            etl_config.sources[1].partitioning,
+            Some(PartitioningConfig {
+                lower_param: "pf".into(),
+                upper_param: "pt".into(),
+                min_value: 0,
+                max_value: 10_000_000_000,
+                min_width: 1_000,
+            })
+        );
+        let partitioning: PartitioningConfig =
+            serde_yaml::from_str("{ lower_param: a, upper_param: b, min_value: 0, max_value: 9 }")?;
+        assert_eq!(partitioning.min_width, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_dag() -> Result<(), anyhow::Error> {
+        let etl_config = shipped_config()?;
+        assert_eq!(etl_config.dag.len(), 3);
+        assert_eq!(etl_config.dag[0].source_name, "default");
+        assert_eq!(etl_config.dag[2].source_name, "propertyfinder");
+        assert_eq!(etl_config.dag[2].menu_pages_per_task, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_clickhouse_section() -> Result<(), anyhow::Error> {
+        let etl_config = shipped_config()?;
+        let clickhouse = etl_config.clickhouse.expect("clickhouse section is missing");
+
+        assert_eq!(clickhouse.connection_name, "clickhouse_db");
+        assert!(!clickhouse.drop_db);
+        assert!(!clickhouse.drop_table);
+        assert_eq!(clickhouse.database.name, "default");
+        assert_eq!(clickhouse.loading_table.name, "property_adverts");
+        assert_eq!(clickhouse.loading_table.columns.first().unwrap(), "ID");
+        assert!(clickhouse.loading_table.columns.contains(&"Coords Lat".to_string()));
+        assert_eq!(clickhouse.filter_table.columns, vec!["url", "source_name"]);
+        assert_eq!(
+            clickhouse.filter_table.prepared_records[0],
+            PreparedFilter {
+                url: "/for-sale/property/uae/?price_min=0&price_max=1000000".into(),
+                source_name: "bayut".into(),
+                begin_page: None,
+                end_page: None,
+            }
+        );
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,259 @@
+use tracing::{info, warn};
+
+use crate::{
+    etl_config_parser::PartitioningConfig,
+    page_walker::{FilterRange, PageWalker},
+};
+
+fn query_param<'a>(url: &'a str, name: &str) -> Option<&'a str> {
+    let (_, query) = url.split_once('?')?;
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Replaces values of the query params or appends missing ones
+pub fn with_query_params(url: &str, params: &[(&str, String)]) -> String {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut pairs: Vec<String> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(String::from)
+        .collect();
+    for (name, value) in params {
+        let pair = format!("{name}={value}");
+        match pairs
+            .iter_mut()
+            .find(|p| p.split('=').next() == Some(*name))
+        {
+            Some(existing) => *existing = pair,
+            None => pairs.push(pair),
+        }
+    }
+    format!("{path}?{}", pairs.join("&"))
+}
+
+/// Value range of a filter url, config bounds stand in for missing params
+fn value_range(url: &str, config: &PartitioningConfig) -> (u64, u64) {
+    let param = |name: &str, default: u64| {
+        query_param(url, name)
+            .and_then(|value| value.parse::<u64>().ok())
+            .unwrap_or(default)
+    };
+    (
+        param(&config.lower_param, config.min_value),
+        param(&config.upper_param, config.max_value),
+    )
+}
+
+/// Halves the value range of a filter until `cards_limit` of every partition fits
+/// `menu.page_limit` pages. Partitions without cards or whose count fails are dropped,
+/// a range is split only when both halves keep `min_width` values
+pub async fn partition_filter(
+    walker: &PageWalker,
+    config: &PartitioningConfig,
+    filter: &FilterRange,
+) -> Vec<FilterRange> {
+    let page_limit = walker.page_limit();
+    let mut partitions = Vec::new();
+    let mut pending = vec![value_range(&filter.filter_url, config)];
+
+    while let Some((lower, upper)) = pending.pop() {
+        let filter_url = with_query_params(
+            &filter.filter_url,
+            &[
+                (&config.lower_param, lower.to_string()),
+                (&config.upper_param, upper.to_string()),
+            ],
+        );
+        let partition = |end_page| FilterRange {
+            filter_url: filter_url.clone(),
+            begin_page: filter.begin_page,
+            end_page,
+        };
+        let count = match walker.count_cards(&filter_url, filter.begin_page == 1).await {
+            Ok(count) => count,
+            Err(err) => {
+                warn!("couldn't count cards of [{filter_url}], partition is dropped: {err:?}");
+                continue;
+            }
+        };
+        let capacity = page_limit.saturating_mul(count.cards_per_page);
+        match count.cards_limit {
+            Some(0) => {
+                walker.forget_first_page(&filter_url);
+                continue;
+            }
+            Some(cards_limit) if count.cards_per_page > 0 && cards_limit > capacity => {
+                // inverted bounds of a url or of the config aren't split,
+                // both halves of the `width + 1` values keep `min_width` of them
+                let width = upper
+                    .checked_sub(lower)
+                    .filter(|width| width / 2 + width % 2 >= config.min_width.max(1));
+                if let Some(width) = width {
+                    walker.forget_first_page(&filter_url);
+                    let middle = lower + width / 2;
+                    pending.push((middle + 1, upper));
+                    pending.push((lower, middle));
+                    continue;
+                }
+                warn!("partition [{filter_url}] has [{cards_limit}] cards over [{capacity}], it is too narrow to split");
+            }
+            _ => {}
+        }
+        partitions.push(partition(walker.pages_of(&filter_url, &count)));
+    }
+    info!("filter [{}] is split into [{}] partitions", filter.filter_url, partitions.len());
+    partitions
+}
+
+/// Partitions filters of the source which reach `menu.page_limit`, others are kept
+pub async fn partition_filters(walker: &PageWalker, filters: &[FilterRange]) -> Vec<FilterRange> {
+    let Some(config) = walker.source_config().partitioning.clone() else {
+        warn!("source [{}] has no partitioning config", walker.source_name());
+        return filters.to_vec();
+    };
+    let mut partitions = Vec::new();
+    for filter in filters {
+        if filter.end_page < walker.page_limit() {
+            partitions.push(filter.clone());
+            continue;
+        }
+        partitions.extend(partition_filter(walker, &config, filter).await);
+    }
+    partitions
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_server::TestServer,
+        test_support::{cards_limit_rule, create_test_walker_with, menu_html},
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_with_query_params() {
+        let params = [("pf", "10".to_string()), ("pt", "20".to_string())];
+        assert_eq!(
+            with_query_params("/en/search?c=1&pf=0&ob=mr", &params),
+            "/en/search?c=1&pf=10&ob=mr&pt=20"
+        );
+        assert_eq!(with_query_params("/for-sale/", &params), "/for-sale/?pf=10&pt=20");
+        assert_eq!(query_param("/a?pf=1&pt=2", "pt"), Some("2"));
+        assert_eq!(query_param("/a?pf=1", "pt"), None);
+    }
+
+    #[tokio::test]
+    async fn test_partition_filters() -> Result<(), anyhow::Error> {
+        // a card per price from 0 to 99 and nine more cards priced 50
+        let prices: Vec<u64> = (0..100).chain([50; 9]).collect();
+        let server = TestServer::start(move |req| {
+            let param = |name: &str, default: u64| {
+                query_param(&req.path, name)
+                    .and_then(|value| value.parse::<u64>().ok())
+                    .unwrap_or(default)
+            };
+            let (lower, upper) = (param("pf", 0), param("pt", u64::MAX));
+            let cards = prices.iter().filter(|p| (lower..=upper).contains(*p)).count();
+            let menu = menu_html(&vec!["/card"; cards.min(2)]);
+            (200, format!(r#"{menu}<span class="count">{cards}</span>"#))
+        })
+        .await;
+
+        let walker = create_test_walker_with(&server.url(), "test_partition_filters", |source| {
+            source.menu.page_limit = 2;
+            source.menu.cards_per_page = 2;
+            source.menu.rules.push(cards_limit_rule());
+            source.partitioning = Some(PartitioningConfig {
+                lower_param: "pf".into(),
+                upper_param: "pt".into(),
+                min_value: 0,
+                max_value: 199,
+                min_width: 1,
+            });
+        });
+
+        let small = FilterRange {
+            filter_url: "/small".into(),
+            begin_page: 1,
+            end_page: 1,
+        };
+        let search = FilterRange {
+            filter_url: "/search?c=1".into(),
+            begin_page: 1,
+            end_page: 2,
+        };
+        let partitions = partition_filters(&walker, &[small.clone(), search]).await;
+        assert_eq!(partitions[0], small);
+
+        let ranges: Vec<(u64, u64, usize)> = partitions[1..]
+            .iter()
+            .map(|p| {
+                assert!(p.filter_url.starts_with("/search?c=1&pf="));
+                let (lower, upper) = value_range(&p.filter_url, walker.source_config().partitioning.as_ref().unwrap());
+                (lower, upper, p.end_page)
+            })
+            .collect();
+        // contiguous ranges over every price, the empty range above 99 is dropped
+        assert_eq!(ranges.first().unwrap().0, 0);
+        assert!(ranges.last().unwrap().1 >= 99);
+        assert!(ranges.windows(2).all(|w| w[0].1 + 1 == w[1].0));
+        for (lower, upper, end_page) in ranges.iter() {
+            let cards = (0..100).chain([50; 9]).filter(|p| (*lower..=*upper).contains(p)).count();
+            assert!(cards > 0);
+            assert_eq!(*end_page, cards.div_ceil(2).min(2));
+            // only the range of the repeated price can't fit
+            assert!(cards <= 4 || (*lower, *upper) == (50, 50), "{lower}..={upper} has {cards}");
+        }
+        assert!(ranges.contains(&(50, 50, 2)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partition_min_width() -> Result<(), anyhow::Error> {
+        let server = TestServer::start(|req| match req.path.as_str() {
+            "/robots.txt" => (200, "User-agent: *\nDisallow: /denied\n".into()),
+            _ => (200, format!(r#"{}<span class="count">100</span>"#, menu_html(&["/card"; 2]))),
+        })
+        .await;
+        let config = PartitioningConfig {
+            lower_param: "pf".into(),
+            upper_param: "pt".into(),
+            min_value: 0,
+            max_value: 39,
+            min_width: 10,
+        };
+        let walker = create_test_walker_with(&server.url(), "test_partition_min_width", |source| {
+            source.menu.page_limit = 2;
+            source.menu.cards_per_page = 2;
+            source.respect_robots_txt = true;
+            source.menu.rules.push(cards_limit_rule());
+        });
+        let partition = |filter_url: &str| {
+            let filter = FilterRange {
+                filter_url: filter_url.into(),
+                begin_page: 1,
+                end_page: 2,
+            };
+            let (walker, config) = (&walker, &config);
+            async move {
+                partition_filter(walker, config, &filter)
+                    .await
+                    .iter()
+                    .map(|p| value_range(&p.filter_url, config))
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        // halves of 20 values keep 10 of them, 19 values aren't split
+        assert_eq!(partition("/search").await, vec![(0, 9), (10, 19), (20, 29), (30, 39)]);
+        assert_eq!(partition("/search?pf=0&pt=18").await, vec![(0, 18)]);
+        assert_eq!(partition("/search?pf=0&pt=19").await, vec![(0, 9), (10, 19)]);
+        assert!(partition("/denied").await.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    ffi::OsString,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use clap::ValueEnum;
+use tracing::info;
+
+use crate::record_sink::{CardRecord, RecordSink, RecordSinkError};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq)]
+pub enum OutputFormat {
+    /// One JSON document keyed by page number, by filter url first for filter runs
+    #[default]
+    Json,
+    /// One card record per line
+    Jsonl,
+}
+
+/// Temp file lives next to the target, so the final rename stays on one filesystem
+pub fn temp_path_for(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(format!(".{}.tmp", std::process::id()));
+    path.with_file_name(file_name)
+}
+
+#[derive(Debug)]
+struct FileSinkState {
+    writer: Option<BufWriter<File>>,
+    group_by_filter: bool,
+    open_filter: Option<String>,
+    filters_count: usize,
+    open_page: Option<usize>,
+    pages_count: usize,
+    cards_in_page: usize,
+}
+
+/// Streams records into a temp file and renames it over the target on `finish`,
+/// a crashed run never leaves a half-written output
+#[derive(Debug)]
+pub struct FileSink {
+    path: PathBuf,
+    temp_path: PathBuf,
+    format: OutputFormat,
+    state: Mutex<FileSinkState>,
+}
+
+impl FileSink {
+    /// With `group_by_filter` the JSON document is keyed by filter url, then by page number
+    pub fn create(
+        path: &Path,
+        format: OutputFormat,
+        group_by_filter: bool,
+    ) -> Result<Self, RecordSinkError> {
+        let temp_path = temp_path_for(path);
+        let mut writer = BufWriter::new(File::create(&temp_path)?);
+        if format == OutputFormat::Json {
+            writer.write_all(b"{")?;
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            temp_path,
+            format,
+            state: Mutex::new(FileSinkState {
+                writer: Some(writer),
+                group_by_filter,
+                open_filter: None,
+                filters_count: 0,
+                open_page: None,
+                pages_count: 0,
+                cards_in_page: 0,
+            }),
+        })
+    }
+}
+
+impl FileSinkState {
+    fn writer(&mut self) -> &mut BufWriter<File> {
+        self.writer.as_mut().expect("file sink is already finished")
+    }
+
+    /// JSON only, keys follow filter and page order as the walker emits them
+    fn open_page(&mut self, filter_url: &str, page_number: usize) -> Result<(), RecordSinkError> {
+        if self.group_by_filter && self.open_filter.as_deref() != Some(filter_url) {
+            self.close_page()?;
+            self.close_filter()?;
+            let separator = if self.filters_count > 0 { "," } else { "" };
+            let key = serde_json::to_string(filter_url)?;
+            write!(self.writer(), "{separator}{key}:{{")?;
+            self.open_filter = Some(filter_url.to_owned());
+            self.filters_count += 1;
+            self.pages_count = 0;
+        }
+        if self.open_page == Some(page_number) {
+            return Ok(());
+        }
+        self.close_page()?;
+        let separator = if self.pages_count > 0 { "," } else { "" };
+        write!(self.writer(), "{separator}\"{page_number}\":[")?;
+        self.open_page = Some(page_number);
+        self.pages_count += 1;
+        self.cards_in_page = 0;
+        Ok(())
+    }
+
+    fn close_page(&mut self) -> Result<(), RecordSinkError> {
+        if self.open_page.take().is_some() {
+            self.writer().write_all(b"]")?;
+        }
+        Ok(())
+    }
+
+    fn close_filter(&mut self) -> Result<(), RecordSinkError> {
+        if self.open_filter.take().is_some() {
+            self.writer().write_all(b"}")?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RecordSink for FileSink {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+        let mut state = self.state.lock().unwrap();
+        match self.format {
+            OutputFormat::Json => {
+                state.open_page(&record.filter_url, record.page_number)?;
+                if state.cards_in_page > 0 {
+                    state.writer().write_all(b",")?;
+                }
+                serde_json::to_writer(state.writer(), &record.tagged_data())?;
+                state.cards_in_page += 1;
+            }
+            OutputFormat::Jsonl => {
+                serde_json::to_writer(state.writer(), &record.tagged_data())?;
+                state.writer().write_all(b"\n")?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn page_finished(
+        &self,
+        filter_url: &str,
+        page_number: usize,
+    ) -> Result<(), RecordSinkError> {
+        if self.format == OutputFormat::Json {
+            let mut state = self.state.lock().unwrap();
+            state.open_page(filter_url, page_number)?;
+            state.close_page()?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&self) -> Result<(), RecordSinkError> {
+        let mut state = self.state.lock().unwrap();
+        if self.format == OutputFormat::Json {
+            state.close_page()?;
+            state.close_filter()?;
+            state.writer().write_all(b"}")?;
+        }
+        let writer = state.writer.take().expect("file sink is already finished");
+        writer.into_inner().map_err(|err| err.into_error())?.sync_all()?;
+        fs::rename(&self.temp_path, &self.path)?;
+        info!(
+            "output [{}] pages [{}] format [{:?}]",
+            self.path.display(),
+            state.pages_count,
+            self.format
+        );
+        Ok(())
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        let unfinished = self.state.get_mut().map(|s| s.writer.is_some()).unwrap_or(true);
+        if unfinished {
+            let _ = fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::transform_html::defs::TransformedData;
+
+    use super::*;
+
+    fn card(filter_url: &str, page_number: usize, id: &str) -> CardRecord {
+        let mut data = TransformedData::create_data_map();
+        data.insert("ID".into(), id.into());
+        CardRecord {
+            filter_url: filter_url.into(),
+            page_number,
+            data,
+        }
+    }
+
+    async fn write_pages(
+        path: &Path,
+        format: OutputFormat,
+        group_by_filter: bool,
+    ) -> Result<(), RecordSinkError> {
+        let sink = FileSink::create(path, format, group_by_filter)?;
+        sink.consume(card("/a", 1, "1")).await?;
+        sink.consume(card("/a", 1, "2")).await?;
+        sink.page_finished("/a", 1).await?;
+        sink.consume(card("/a", 2, "3")).await?;
+        sink.page_finished("/a", 2).await?;
+        sink.page_finished("/a", 3).await?;
+        if group_by_filter {
+            sink.consume(card("/b", 1, "4")).await?;
+            sink.page_finished("/b", 1).await?;
+        }
+        assert!(!path.exists());
+        sink.finish().await
+    }
+
+    #[tokio::test]
+    async fn test_file_sink() -> Result<(), anyhow::Error> {
+        let dir = std::env::temp_dir().join(format!("output_writer_{}", std::process::id()));
+        fs::create_dir_all(&dir)?;
+
+        let json_path = dir.join("output.json");
+        write_pages(&json_path, OutputFormat::Json, false).await?;
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&json_path)?)?;
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "1": [{"ID": "1", "filter_url": "/a"}, {"ID": "2", "filter_url": "/a"}],
+                "2": [{"ID": "3", "filter_url": "/a"}],
+                "3": [],
+            })
+        );
+
+        let grouped_path = dir.join("grouped.json");
+        write_pages(&grouped_path, OutputFormat::Json, true).await?;
+        let value: serde_json::Value = serde_json::from_str(&fs::read_to_string(&grouped_path)?)?;
+        assert_eq!(value["/a"]["2"][0]["ID"], "3");
+        assert_eq!(value["/a"]["3"], serde_json::json!([]));
+        assert_eq!(value["/b"]["1"][0]["filter_url"], "/b");
+
+        let jsonl_path = dir.join("output.jsonl");
+        write_pages(&jsonl_path, OutputFormat::Jsonl, false).await?;
+        let lines = fs::read_to_string(&jsonl_path)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], serde_json::json!({"ID": "3", "filter_url": "/a"}));
+        assert!(!temp_path_for(&json_path).exists());
+
+        let crashed_path = dir.join("crashed.json");
+        {
+            let sink = FileSink::create(&crashed_path, OutputFormat::Json, false)?;
+            sink.consume(card("/a", 1, "1")).await?;
+        }
+        assert!(!crashed_path.exists());
+        assert!(!temp_path_for(&crashed_path).exists());
+
+        assert!(FileSink::create(&dir.join("missing/output.json"), OutputFormat::Json, false).is_err());
+
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,17 +1,24 @@
 use crate::{
+    config_loader::{load_config, ConfigLoadError},
+    rate_limiter::RateLimit,
+    record_sink::*,
+    robots_txt::RobotsCache,
     request_maker::*,
-    transform_html::{self, defs::*, *},
+    transform_html::{self, compiled_rule::*, defs::*, *},
 };
 use anyhow::Error;
 use derive_more::Display;
+use futures::{stream, StreamExt, TryStreamExt};
 use thiserror::Error;
-use tracing::info;
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
 
 use super::etl_config_parser::*;
 use serde_yaml;
 use std::{
     borrow::{Borrow, BorrowMut, Cow},
     cell::RefCell,
+    collections::HashMap,
     fmt::Debug,
     fs::{self, File},
     io::BufReader,
@@ -29,10 +36,40 @@ pub struct PageWalker {
     source_config_idx: usize,
     request_maker: RequestMaker,
     menu_page_url_sub: String,
+    /// `menu.rules` and `card.rules` parsed once
+    menu_rules: Vec<CompiledRule>,
+    card_rules: Vec<CompiledRule>,
     max_depth_level: usize,
+    /// global cap of requests in flight
+    request_slots: Semaphore,
+    /// None when the source doesn't respect robots.txt
+    robots: Option<RobotsCache>,
+    /// ends the walk of a filter on the first menu page without `menu_items`
+    stop_on_empty_page: bool,
+    /// first menu pages parsed by `count_cards`, taken by the walk of the filter
+    first_pages: Mutex<HashMap<String, DataMap>>,
 }
 
-type ConsumerType = dashmap::DashMap<usize, Vec<DataMap>>;
+/// Result count of a filter from its first menu page
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardCount {
+    pub cards_limit: Option<usize>,
+    pub cards_per_page: usize,
+}
+
+/// Cards of a menu page, `menu_items` also counts cards refused by robots.txt
+struct MenuPage {
+    menu_items: usize,
+    cards: Vec<DataMap>,
+}
+
+/// One filter url of a source with its page range
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterRange {
+    pub filter_url: String,
+    pub begin_page: usize,
+    pub end_page: usize,
+}
 
 #[derive(Error, Debug)]
 pub enum PageWalkerError {
@@ -42,12 +79,18 @@ pub enum PageWalkerError {
     SerdeYamlError(#[from] serde_yaml::Error),
     #[error("couldn't parse config {0}")]
     ParseConfigError(PathBuf, #[source] Box<PageWalkerError>),
+    #[error("couldn't load config {0}")]
+    ConfigLoadError(#[from] ConfigLoadError),
     #[error("couldn't find source by name {0}")]
     SourceConfigNotFound(String),
     #[error("couldn't create or use RequestMaker")]
     RequestMakerError(#[from] RequestMakerError),
     #[error("couldn't create or use RequestMaker")]
     TransformHtmlError(#[from] TransformError),
+    #[error("couldn't pass record to sink")]
+    RecordSinkError(#[from] RecordSinkError),
+    #[error("url {0} is disallowed by robots.txt")]
+    DisallowedByRobotsTxt(String),
 }
 
 impl PageWalker {
@@ -65,7 +108,30 @@ impl PageWalker {
                 .page_url_sub
                 .as_str(),
         );
+        let menu_rules = compile_rules(&etl_config.sources[source_config_idx].menu.rules)?;
+        let card_rules = compile_rules(&etl_config.sources[source_config_idx].card.rules)?;
         let retries = &etl_config.http.retries;
+        let rate_limit = etl_config.sources[source_config_idx]
+            .rate_limit
+            .as_ref()
+            .or(etl_config.http.rate_limit.as_ref())
+            .map(|r| RateLimit {
+                requests_per_second: r.requests_per_second,
+                burst: r.burst,
+                min_delay: Duration::from_millis(r.min_delay_ms),
+            });
+        let robots = etl_config.sources[source_config_idx]
+            .respect_robots_txt
+            .then(|| {
+                let user_agent = etl_config
+                    .http
+                    .headers
+                    .get("user-agent")
+                    .cloned()
+                    .or_else(|| RequestMakerConfig::default().headers.remove("user-agent"))
+                    .unwrap_or_default();
+                RobotsCache::create(user_agent)
+            });
         let request_maker = RequestMaker::create(RequestMakerConfig {
             headers: etl_config.http.headers.clone(),
 
@@ -73,6 +139,7 @@ impl PageWalker {
             max_retries: retries.max_retries,
             timeout: Duration::from_secs(retries.timeout.into()),
             status_forcelist: retries.status_forcelist.clone(),
+            rate_limit,
         })?;
 
         Ok(Self {
@@ -81,8 +148,14 @@ impl PageWalker {
             etl_config,
             source_config_idx,
             menu_page_url_sub,
+            menu_rules,
+            card_rules,
             request_maker,
             max_depth_level,
+            request_slots: Semaphore::new(Semaphore::MAX_PERMITS),
+            robots,
+            stop_on_empty_page: false,
+            first_pages: Mutex::default(),
         })
     }
 
@@ -97,10 +170,208 @@ impl PageWalker {
             .map_err(|err| PageWalkerError::ParseConfigError(dbg!(abs_path), err.into()))
     }
 
+    /// Caps requests in flight over all menu and card pages of the walker
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.request_slots = Semaphore::new(max_concurrency.clamp(1, Semaphore::MAX_PERMITS));
+        self
+    }
+
+    pub fn with_stop_on_empty_page(mut self, stop_on_empty_page: bool) -> Self {
+        self.stop_on_empty_page = stop_on_empty_page;
+        self
+    }
+
+    /// YAML, JSON or TOML by the extension with `include` and `${ENV_VAR:-default}`
     pub fn parse_config(etl_config_path: &Path) -> Result<EtlConfig, PageWalkerError> {
-        let file = File::open(etl_config_path)?;
-        let etl_config: EtlConfig = serde_yaml::from_reader(BufReader::new(file))?;
-        Ok(etl_config)
+        Ok(load_config(etl_config_path)?)
+    }
+
+    /// Filters file is a YAML or JSON list of `PreparedFilter`
+    pub fn parse_filters(filters_path: &Path) -> Result<Vec<PreparedFilter>, PageWalkerError> {
+        let file = File::open(filters_path)?;
+        let filters: Vec<PreparedFilter> = serde_yaml::from_reader(BufReader::new(file))?;
+        Ok(filters)
+    }
+
+    /// From the `dag` entry of the source or the `default` one
+    pub fn menu_pages_per_task(&self) -> Option<usize> {
+        let dag = &self.etl_config.dag;
+        dag.iter()
+            .find(|d| d.source_name == self.source_name)
+            .or_else(|| dag.iter().find(|d| d.source_name == "default"))
+            .map(|d| d.menu_pages_per_task)
+    }
+
+    pub fn source_name(&self) -> &str {
+        &self.source_name
+    }
+
+    /// Urls refused by robots.txt so far
+    pub fn skipped_urls(&self) -> Vec<String> {
+        self.robots
+            .as_ref()
+            .map(RobotsCache::skipped_urls)
+            .unwrap_or_default()
+    }
+
+    pub fn prepared_filters(&self) -> Vec<PreparedFilter> {
+        self.etl_config
+            .clickhouse
+            .as_ref()
+            .map(|clickhouse| clickhouse.filter_table.prepared_records.clone())
+            .unwrap_or_default()
+    }
+
+    /// Filters without a source name or with the name of the current source
+    pub fn own_filters<'a>(&'a self, filters: &'a [PreparedFilter]) -> impl Iterator<Item = &'a PreparedFilter> {
+        filters
+            .iter()
+            .filter(|f| f.source_name.is_empty() || f.source_name == self.source_name)
+    }
+
+    /// Keeps filters of the current source, falls back to `menu.default_url` without any
+    fn source_filters<'a>(&'a self, filters: &'a [PreparedFilter]) -> Vec<Cow<'a, PreparedFilter>> {
+        let source_filters: Vec<Cow<PreparedFilter>> = self.own_filters(filters).map(Cow::Borrowed).collect();
+        if !source_filters.is_empty() {
+            return source_filters;
+        }
+        if !filters.is_empty() {
+            warn!("none of [{}] filters is of source [{}], `menu.default_url` is crawled", filters.len(), self.source_name);
+        }
+        vec![Cow::Owned(PreparedFilter {
+            url: self.source_config().menu.default_url.clone(),
+            source_name: self.source_name.clone(),
+            begin_page: None,
+            end_page: None,
+        })]
+    }
+
+    pub fn filter_ranges(
+        &self,
+        filters: &[PreparedFilter],
+        begin: usize,
+        end: usize,
+    ) -> Vec<FilterRange> {
+        self.source_filters(filters)
+            .iter()
+            .map(|f| FilterRange {
+                filter_url: f.url.clone(),
+                begin_page: f.begin_page.unwrap_or(begin),
+                end_page: f.end_page.unwrap_or(end),
+            })
+            .collect()
+    }
+
+    /// Like `filter_ranges`, the end page of a filter without one comes from `count_pages`,
+    /// filters whose first page couldn't be read are skipped
+    pub async fn all_pages_ranges(&self, filters: &[PreparedFilter], begin: usize) -> Vec<FilterRange> {
+        let mut ranges = Vec::new();
+        for f in self.source_filters(filters) {
+            let begin_page = f.begin_page.unwrap_or(begin);
+            let end_page = match f.end_page {
+                Some(end_page) => end_page,
+                None => match self.count_pages(&f.url, begin_page == 1).await {
+                    Some(end_page) => end_page,
+                    None => continue,
+                },
+            };
+            ranges.push(FilterRange {
+                filter_url: f.url.clone(),
+                begin_page,
+                end_page,
+            });
+        }
+        ranges
+    }
+
+    pub fn page_limit(&self) -> usize {
+        usize::try_from(self.source_config().menu.page_limit).unwrap_or_default()
+    }
+
+    /// `cards_limit` and cards per page of the first menu page of a filter,
+    /// with `keep_first_page` the page is kept for a walk of the filter from page 1
+    pub async fn count_cards(&self, filter_url: &str, keep_first_page: bool) -> Result<CardCount, PageWalkerError> {
+        if let Some(first_page) = self.first_pages.lock().unwrap().get(filter_url) {
+            return Ok(self.card_count(filter_url, first_page));
+        }
+        let first_page = self.parse_menu_page(filter_url, 1).await?;
+        let count = self.card_count(filter_url, &first_page);
+        if keep_first_page {
+            self.first_pages
+                .lock()
+                .unwrap()
+                .insert(filter_url.to_owned(), first_page);
+        }
+        Ok(count)
+    }
+
+    /// Drops the first page kept by `count_cards` for a filter which won't be walked
+    pub fn forget_first_page(&self, filter_url: &str) {
+        self.first_pages.lock().unwrap().remove(filter_url);
+    }
+
+    /// Keeps first pages only of the ranges which are walked from page 1
+    pub fn retain_first_pages(&self, ranges: &[FilterRange]) {
+        self.first_pages.lock().unwrap().retain(|filter_url, _| {
+            ranges
+                .iter()
+                .any(|range| range.filter_url == *filter_url && range.begin_page == 1)
+        });
+    }
+
+    /// `cards_limit` is an `Int` (`type: int`) or a text of digits only
+    fn card_count(&self, filter_url: &str, first_page: &DataMap) -> CardCount {
+        let cards_limit = match first_page.get("cards_limit") {
+            Some(TransformedData::Value(value)) => {
+                let value = value.trim();
+                let cards_limit = value
+                    .chars()
+                    .all(|ch| ch.is_ascii_digit())
+                    .then(|| value.parse::<usize>().ok())
+                    .flatten();
+                if cards_limit.is_none() {
+                    warn!("cards_limit [{value}] of [{filter_url}] isn't a number, isolate it with regex_sub_value or use type: int");
+                }
+                cards_limit
+            }
+            Some(TransformedData::Int(value)) => usize::try_from(*value).ok(),
+            _ => None,
+        };
+        let cards_per_page = match usize::try_from(self.source_config().menu.cards_per_page) {
+            Ok(cards_per_page) if cards_per_page > 0 => cards_per_page,
+            _ => Self::menu_items(first_page).len(),
+        };
+        CardCount {
+            cards_limit,
+            cards_per_page,
+        }
+    }
+
+    /// Pages of the count clamped to `menu.page_limit`, only the first page when the count is unknown
+    pub fn pages_of(&self, filter_url: &str, count: &CardCount) -> usize {
+        let page_limit = self.page_limit();
+        let pages = match count.cards_limit {
+            Some(cards_limit) if count.cards_per_page > 0 => cards_limit.div_ceil(count.cards_per_page),
+            Some(0) => 0,
+            _ => {
+                warn!("cards count {count:?} of [{filter_url}] is unknown, only the first page is taken");
+                1
+            }
+        };
+        info!("filter [{filter_url}] has {count:?} on [{pages}] pages, page limit [{page_limit}]");
+        pages.min(page_limit)
+    }
+
+    /// `cards_limit` of the first menu page over cards per page, clamped to `menu.page_limit`.
+    /// None when the first page couldn't be read
+    pub async fn count_pages(&self, filter_url: &str, keep_first_page: bool) -> Option<usize> {
+        match self.count_cards(filter_url, keep_first_page).await {
+            Ok(count) => Some(self.pages_of(filter_url, &count)),
+            Err(err) => {
+                warn!("couldn't count pages of [{filter_url}], the filter is skipped: {err:?}");
+                None
+            }
+        }
     }
 
     pub fn extract_source_config(
@@ -120,6 +391,10 @@ impl PageWalker {
         ))
     }
 
+    pub fn etl_config(&self) -> &EtlConfig {
+        &self.etl_config
+    }
+
     pub fn source_config(&self) -> &SourceConfig {
         &self.etl_config.sources[self.source_config_idx]
     }
@@ -142,21 +417,34 @@ impl PageWalker {
     async fn extract_data(
         &self,
         url: &str,
-        rules: &Vec<ParserTransfromRule>,
+        rules: &[CompiledRule],
     ) -> Result<DataMap, PageWalkerError> {
-        info!("read page [{url}]");
-        let response = self
-            .request_maker
-            .request_text(&RequestParams {
-                method: "GET".to_owned(),
-                url: url.to_owned(),
-                ..Default::default()
-            })
-            .await?;
+        if let Some(robots) = &self.robots {
+            if !robots.is_allowed(&self.request_maker, url).await {
+                warn!("url [{url}] is disallowed by robots.txt");
+                return Err(PageWalkerError::DisallowedByRobotsTxt(url.to_owned()));
+            }
+        }
+        let response = {
+            let _permit = self
+                .request_slots
+                .acquire()
+                .await
+                .expect("request slots are never closed");
+            info!("read page [{url}]");
+            self.request_maker
+                .request_text(&RequestParams {
+                    method: "GET".to_owned(),
+                    url: url.to_owned(),
+                    ..Default::default()
+                })
+                .await?
+        };
         let data = transform_html_map(
             &response,
             rules,
             &TransformSettings {
+                normalize: self.source_config().normalize.clone(),
                 ..Default::default()
             },
         )?;
@@ -169,7 +457,7 @@ impl PageWalker {
         page_number: usize,
     ) -> Result<DataMap, PageWalkerError> {
         let url = self.sub_page_number(filter_url, page_number);
-        self.extract_data(&url, &self.source_config().menu.rules)
+        self.extract_data(&url, &self.menu_rules)
             .await
     }
 
@@ -180,60 +468,125 @@ impl PageWalker {
         } else {
             url_combine(&self.source_config().root_url, &url_part)
         };
-        self.extract_data(&url, &self.source_config().card.rules)
+        self.extract_data(&url, &self.card_rules)
             .await
     }
 
-    pub async fn walk_on_menu_page(
+    fn menu_items(menu: &DataMap) -> &[TransformedData] {
+        match menu.get("menu_items") {
+            Some(TransformedData::List(menu_items)) => menu_items,
+            _ => &[],
+        }
+    }
+
+    /// Card pages are fetched `concurrency.cards` at a time and keep menu order,
+    /// cards disallowed by robots.txt are left out
+    async fn read_menu_page(
         &self,
         filter_url: &str,
         num: usize,
-        consumer: &ConsumerType,
-    ) -> Result<(), PageWalkerError> {
-        let menu = self.parse_menu_page(filter_url, num).await?;
-        let menu_items = menu["menu_items"].exract_list();
-
-        if menu_items.is_empty() {
-            consumer.insert(num, Vec::new());
-        }
-
-        let mut card_data_list = Vec::new();
+    ) -> Result<MenuPage, PageWalkerError> {
+        let first_page = match num {
+            1 => self.first_pages.lock().unwrap().remove(filter_url),
+            _ => None,
+        };
+        let menu = match first_page {
+            Some(menu) => menu,
+            None => self.parse_menu_page(filter_url, num).await?,
+        };
+        let menu_items = Self::menu_items(&menu);
 
-        for ele in menu_items.iter() {
+        let card_urls = menu_items.iter().map(|ele| {
             info!("ele [{ele:#?}]");
-            let url = ele
-                .exract_dict()
+            ele.exract_dict()
                 .get("url")
                 .expect("couldn't found 'url'")
-                .exract_value();
-            let card_data = self.parse_card_page(url).await?;
-            card_data_list.push(card_data);
+                .exract_value()
+        });
+        stream::iter(card_urls)
+            .map(|url| async move {
+                match self.parse_card_page(url).await {
+                    Err(PageWalkerError::DisallowedByRobotsTxt(_)) => Ok(None),
+                    card => card.map(Some),
+                }
+            })
+            .buffered(self.source_config().concurrency.cards.max(1))
+            .try_filter_map(|card| async move { Ok(card) })
+            .try_collect()
+            .await
+            .map(|cards| MenuPage {
+                menu_items: menu_items.len(),
+                cards,
+            })
+    }
+
+    async fn emit_page(
+        filter_url: &str,
+        num: usize,
+        cards: Vec<DataMap>,
+        sink: &dyn RecordSink,
+    ) -> Result<(), PageWalkerError> {
+        for card_data in cards {
+            sink.consume(CardRecord {
+                filter_url: filter_url.to_owned(),
+                page_number: num,
+                data: card_data,
+            })
+            .await?;
         }
-        consumer.insert(num, card_data_list);
+        sink.page_finished(filter_url, num).await?;
         Ok(())
     }
 }
 
+/// Menu pages are crawled `concurrency.menu_pages` at a time, records reach the sink in page order.
+/// Page errors are logged and skipped, sink errors stop the walk.
+/// With `stop_on_empty_page` the walk ends before the first page without `menu_items`
 pub async fn walk(
     walker: &PageWalker,
     filter_url: &str,
     begin: usize,
     end: usize,
-    consumer: &ConsumerType,
-) {
-    let mut result_group = Vec::with_capacity(end - begin + 2);
+    sink: &dyn RecordSink,
+) -> Result<(), PageWalkerError> {
+    let mut pages = stream::iter(begin..end + 1)
+        .map(|num| async move { (num, walker.read_menu_page(filter_url, num).await) })
+        .buffered(walker.source_config().concurrency.menu_pages.max(1));
 
-    for num in begin..end + 1 {
-        // let slf = self_ref.clone().get_mut();
-        result_group.push(walker.walk_on_menu_page(filter_url, num, consumer));
+    while let Some((num, menu_page)) = pages.next().await {
+        match menu_page {
+            Ok(menu_page) if walker.stop_on_empty_page && menu_page.menu_items == 0 => {
+                info!("page [{num}] of [{filter_url}] has no menu items, walk is stopped");
+                break;
+            }
+            Ok(menu_page) => PageWalker::emit_page(filter_url, num, menu_page.cards, sink).await?,
+            Err(err) => warn!("page [{num}] of [{filter_url}] is skipped: {err:?}"),
+        }
     }
-    for ele in result_group {
-        ele.await;
+    Ok(())
+}
+
+/// Crawls filters one after another
+pub async fn walk_filters(
+    walker: &PageWalker,
+    filters: &[FilterRange],
+    sink: &dyn RecordSink,
+) -> Result<(), PageWalkerError> {
+    for filter in filters {
+        info!("walk filter [{filter:?}]");
+        walk(walker, &filter.filter_url, filter.begin_page, filter.end_page, sink).await?;
     }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::{
+        test_server::TestServer,
+        test_support::{cards_limit_rule, create_test_walker, create_test_walker_with, menu_html},
+        transform_html::value_type::ValueType,
+    };
+
     use super::*;
 
     #[test]
@@ -244,4 +597,264 @@ mod tests {
         }
         assert_eq!(vars.2, true);
     }
+
+    #[tokio::test]
+    async fn test_walk() -> Result<(), anyhow::Error> {
+        let server = TestServer::with_pages(vec![
+            ("/search?page=1".into(), menu_html(&["/card/1", "/card/2"])),
+            ("/search?page=2".into(), menu_html(&[])),
+            ("/search?page=3".into(), menu_html(&["/card/3"])),
+            ("/card/1".into(), "<h1>One</h1>".into()),
+            ("/card/2".into(), "<h1>Two</h1>".into()),
+            ("/card/3".into(), "<h1>Three</h1>".into()),
+        ])
+        .await;
+        let walker = create_test_walker(&server.url(), "test_walk");
+
+        let sink = MemorySink::default();
+        walk(&walker, "/search", 1, 3, &sink).await?;
+
+        let pages = sink.into_pages();
+        let titles = |page: usize| {
+            pages[&("/search".to_string(), page)]
+                .iter()
+                .map(|card| card["Title"].exract_value().clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles(1), vec!["One", "Two"]);
+        assert!(titles(2).is_empty());
+        assert_eq!(titles(3), vec!["Three"]);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 6);
+        assert!(requests.iter().all(|req| req.method == "GET" && req.body.is_empty()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_ranges() {
+        let walker = create_test_walker("http://localhost", "test_filter_ranges");
+        let filter = |url: &str, source_name: &str, end_page: Option<usize>| PreparedFilter {
+            url: url.into(),
+            source_name: source_name.into(),
+            begin_page: None,
+            end_page,
+        };
+
+        assert_eq!(
+            walker.filter_ranges(&[filter("/other", "bayut", None)], 1, 2),
+            vec![FilterRange {
+                filter_url: "/search".into(),
+                begin_page: 1,
+                end_page: 2
+            }]
+        );
+        assert_eq!(
+            walker.filter_ranges(
+                &[
+                    filter("/a", "stub", Some(5)),
+                    filter("/other", "bayut", None),
+                    filter("/b", "", None),
+                ],
+                1,
+                2
+            ),
+            vec![
+                FilterRange {
+                    filter_url: "/a".into(),
+                    begin_page: 1,
+                    end_page: 5
+                },
+                FilterRange {
+                    filter_url: "/b".into(),
+                    begin_page: 1,
+                    end_page: 2
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_walk_filters() -> Result<(), anyhow::Error> {
+        let server = TestServer::with_pages(vec![
+            ("/a?page=1".into(), menu_html(&["/card/1"])),
+            ("/b?page=1".into(), menu_html(&["/card/2"])),
+            ("/b?page=2".into(), menu_html(&["/card/1"])),
+            ("/card/1".into(), "<h1>One</h1>".into()),
+            ("/card/2".into(), "<h1>Two</h1>".into()),
+        ])
+        .await;
+        let walker = create_test_walker(&server.url(), "test_walk_filters");
+        let dir = std::env::temp_dir().join(format!("page_walker_{}", std::process::id()));
+        let filters_path = dir.join("test_walk_filters.filters.yaml");
+        fs::write(
+            &filters_path,
+            "- url: /a\n- url: /b\n  source_name: stub\n  end_page: 2\n- url: /c\n  source_name: bayut\n",
+        )?;
+        let filters = PageWalker::parse_filters(&filters_path)?;
+
+        let sink = MemorySink::default();
+        walk_filters(&walker, &walker.filter_ranges(&filters, 1, 1), &sink).await?;
+
+        let pages = sink.into_pages();
+        assert_eq!(
+            pages.keys().cloned().collect::<Vec<_>>(),
+            vec![("/a".into(), 1), ("/b".into(), 1), ("/b".into(), 2)]
+        );
+        assert_eq!(pages[&("/b".into(), 2)][0]["Title"], "One".into());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_walk_robots_txt() -> Result<(), anyhow::Error> {
+        let server = TestServer::with_pages(vec![
+            (
+                "/robots.txt".into(),
+                "User-agent: *\nDisallow: /card/2\nDisallow: /search?page=2$\nCrawl-delay: 0.05\n".into(),
+            ),
+            ("/search?page=1".into(), menu_html(&["/card/1", "/card/2"])),
+            ("/search?page=2".into(), menu_html(&["/card/1"])),
+            ("/card/1".into(), "<h1>One</h1>".into()),
+            ("/card/2".into(), "<h1>Two</h1>".into()),
+        ])
+        .await;
+        let walker = create_test_walker_with(&server.url(), "test_walk_robots_txt", |source| {
+            source.respect_robots_txt = true
+        });
+
+        let sink = MemorySink::default();
+        walk(&walker, "/search", 1, 2, &sink).await?;
+
+        let pages = sink.into_pages();
+        assert_eq!(pages.keys().cloned().collect::<Vec<_>>(), vec![("/search".into(), 1)]);
+        assert_eq!(pages[&("/search".into(), 1)].len(), 1);
+        assert_eq!(
+            walker.skipped_urls(),
+            vec![format!("{}/card/2", server.url()), format!("{}/search?page=2", server.url())]
+        );
+        let paths: Vec<String> = server.requests().into_iter().map(|req| req.path).collect();
+        assert_eq!(paths, vec!["/robots.txt", "/search?page=1", "/card/1"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_all_pages() -> Result<(), anyhow::Error> {
+        let menu = |cards: &[&str], cards_limit: &str| {
+            format!(r#"{}<span class="count">{cards_limit} results</span>"#, menu_html(cards))
+        };
+        let server = TestServer::with_pages(vec![
+            ("/robots.txt".into(), "User-agent: *\nDisallow: /denied\n".into()),
+            ("/a?page=1".into(), menu(&["/card/1", "/card/2"], "1,005")),
+            ("/a?page=2".into(), menu(&["/card/1"], "1,005")),
+            ("/a?page=3".into(), menu(&[], "1,005")),
+            ("/a?page=4".into(), menu(&["/card/2"], "1,005")),
+            ("/b?page=1".into(), menu(&["/card/1", "/card/2"], "3")),
+            ("/c?page=1".into(), menu(&["/card/1"], "")),
+            ("/card/1".into(), "<h1>One</h1>".into()),
+            ("/card/2".into(), "<h1>Two</h1>".into()),
+        ])
+        .await;
+        let walker = create_test_walker_with(&server.url(), "test_all_pages", |source| {
+            source.concurrency.menu_pages = 2;
+            source.respect_robots_txt = true;
+            source.menu.rules.push(ParserTransfromRule {
+                regex_sub_value: vec!["(.*) results".into(), r"\1".into()],
+                value_type: Some(ValueType::Int),
+                ..cards_limit_rule()
+            });
+        })
+        .with_stop_on_empty_page(true);
+
+        assert_eq!(walker.count_pages("/a", true).await, Some(100));
+        assert_eq!(walker.count_pages("/b", true).await, Some(2));
+        assert_eq!(walker.count_pages("/c", true).await, Some(1));
+        assert_eq!(walker.count_pages("/missing", true).await, Some(1));
+        assert_eq!(walker.count_pages("/denied", true).await, None);
+
+        let filter = |url: &str, end_page: Option<usize>| PreparedFilter {
+            url: url.into(),
+            source_name: String::new(),
+            begin_page: None,
+            end_page,
+        };
+        let ranges = walker
+            .all_pages_ranges(&[filter("/a", None), filter("/denied", None), filter("/b", None), filter("/c", Some(1))], 1)
+            .await;
+        assert_eq!(
+            ranges.iter().map(|r| (r.filter_url.as_str(), r.end_page)).collect::<Vec<_>>(),
+            vec![("/a", 100), ("/b", 2), ("/c", 1)]
+        );
+
+        let sink = MemorySink::default();
+        walk_filters(&walker, &ranges, &sink).await?;
+        assert_eq!(
+            sink.into_pages().keys().cloned().collect::<Vec<_>>(),
+            vec![("/a".into(), 1), ("/a".into(), 2), ("/b".into(), 1), ("/c".into(), 1)]
+        );
+        assert!(!server.requests().iter().any(|req| req.path == "/a?page=5"));
+        // first pages are fetched once to count and to crawl
+        assert_eq!(server.requests().iter().filter(|req| req.path == "/a?page=1").count(), 1);
+
+        // only the counted page of `/missing` isn't walked
+        walker.retain_first_pages(&ranges);
+        assert!(walker.first_pages.lock().unwrap().is_empty());
+        walker.count_pages("/b", false).await;
+        assert!(walker.first_pages.lock().unwrap().is_empty());
+        walker.count_pages("/a", true).await;
+        walker.retain_first_pages(&[FilterRange {
+            filter_url: "/a".into(),
+            begin_page: 2,
+            end_page: 3,
+        }]);
+        assert!(walker.first_pages.lock().unwrap().is_empty());
+
+        let count = |cards_limit: TransformedData| {
+            let first_page: DataMap = Box::new(HashMap::from([("cards_limit".to_owned(), cards_limit)]));
+            walker.card_count("/a", &first_page).cards_limit
+        };
+        assert_eq!(count(TransformedData::Int(1005)), Some(1005));
+        assert_eq!(count(TransformedData::Value(" 1005 ".into())), Some(1005));
+        assert_eq!(count(TransformedData::Value("1 - 25 of 1,005".into())), None);
+        Ok(())
+    }
+
+    struct OrderSink(Mutex<Vec<(usize, String)>>);
+
+    #[async_trait::async_trait]
+    impl RecordSink for OrderSink {
+        async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+            let title = record.data["Title"].exract_value().clone();
+            self.0.lock().unwrap().push((record.page_number, title));
+            Ok(())
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_walk_concurrency() -> Result<(), anyhow::Error> {
+        let mut pages = Vec::new();
+        for page in 1..=4 {
+            let cards: Vec<String> = (1..=5).map(|card| format!("/card/{page}/{card}")).collect();
+            let cards_ref: Vec<&str> = cards.iter().map(String::as_str).collect();
+            pages.push((format!("/search?page={page}"), menu_html(&cards_ref)));
+            for card in cards.iter() {
+                pages.push((card.clone(), format!("<h1>{card}</h1>")));
+            }
+        }
+        let server = TestServer::with_pages(pages).await;
+        server.set_delay(Duration::from_millis(30));
+
+        let walker = create_test_walker_with(&server.url(), "test_walk_concurrency", |source| {
+            source.concurrency = ConcurrencyConfig { menu_pages: 2, cards: 3 }
+        })
+        .with_max_concurrency(4);
+        let sink = OrderSink(Mutex::new(Vec::new()));
+        walk(&walker, "/search", 1, 4, &sink).await?;
+
+        let expected: Vec<(usize, String)> = (1..=4)
+            .flat_map(|page| (1..=5).map(move |card| (page, format!("/card/{page}/{card}"))))
+            .collect();
+        assert_eq!(*sink.0.lock().unwrap(), expected);
+        assert!(server.max_in_flight() > 1);
+        assert!(server.max_in_flight() <= 4);
+        Ok(())
+    }
 }
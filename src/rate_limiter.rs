@@ -0,0 +1,167 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use tokio::time::Instant;
+use tracing::debug;
+
+/// Longest gap between two requests to one host, larger delays are clamped to it
+pub const MAX_DELAY: Duration = Duration::from_secs(10 * 60);
+
+/// Pacing of requests to one host
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// zero disables the token bucket, only `min_delay` is kept
+    pub requests_per_second: f64,
+    /// requests allowed at once before pacing starts
+    pub burst: u32,
+    /// gap between two request starts
+    pub min_delay: Duration,
+}
+
+#[derive(Debug)]
+struct HostSchedule {
+    /// theoretical arrival time of the next request with an empty bucket
+    tat: Instant,
+    last_request: Option<Instant>,
+    min_delay: Duration,
+}
+
+/// Token bucket per host, requests reserve a start time under the lock and sleep outside of it
+#[derive(Debug)]
+pub struct HostRateLimiter {
+    rate_limit: Option<RateLimit>,
+    hosts: Mutex<HashMap<String, HostSchedule>>,
+}
+
+impl HostRateLimiter {
+    pub fn create(rate_limit: Option<RateLimit>) -> Self {
+        Self {
+            rate_limit,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn new_schedule(&self) -> HostSchedule {
+        HostSchedule {
+            tat: Instant::now(),
+            last_request: None,
+            min_delay: self
+                .rate_limit
+                .as_ref()
+                .map(|r| r.min_delay)
+                .unwrap_or_default(),
+        }
+    }
+
+    fn reserve(&self, host: &str) -> Instant {
+        let now = Instant::now();
+        let mut hosts = self.hosts.lock().unwrap();
+        let schedule = hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| self.new_schedule());
+
+        let mut start = now;
+        if let Some(rate_limit) = self.rate_limit.as_ref().filter(|r| r.requests_per_second > 0.0) {
+            let interval = Duration::try_from_secs_f64(1.0 / rate_limit.requests_per_second).map_or(MAX_DELAY, |interval| interval.min(MAX_DELAY));
+            let tolerance = interval * rate_limit.burst.saturating_sub(1);
+            let tat = schedule.tat.max(now);
+            start = start.max(tat.checked_sub(tolerance).unwrap_or(now));
+            schedule.tat = tat + interval;
+        }
+        if let Some(last_request) = schedule.last_request {
+            start = start.max(last_request + schedule.min_delay);
+        }
+        schedule.last_request = Some(start);
+        start
+    }
+
+    /// Keeps the larger of the current and the given gap for the host, e.g. robots.txt `Crawl-delay`
+    pub fn raise_min_delay(&self, host: &str, min_delay: Duration) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let schedule = hosts
+            .entry(host.to_owned())
+            .or_insert_with(|| self.new_schedule());
+        schedule.min_delay = schedule.min_delay.max(min_delay);
+    }
+
+    /// Waits until the host is allowed to get one more request
+    pub async fn acquire(&self, host: &str) {
+        let start = self.reserve(host);
+        if start > Instant::now() {
+            debug!("host [{host}] is paced for [{:?}]", start - Instant::now());
+            tokio::time::sleep_until(start).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn offsets(limiter: &HostRateLimiter, host: &str, count: usize) -> Vec<u128> {
+        let begin = Instant::now();
+        let mut offsets = Vec::new();
+        for _ in 0..count {
+            limiter.acquire(host).await;
+            offsets.push((Instant::now() - begin).as_millis());
+        }
+        offsets
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket() {
+        let limiter = HostRateLimiter::create(Some(RateLimit {
+            requests_per_second: 2.0,
+            burst: 2,
+            min_delay: Duration::ZERO,
+        }));
+        assert_eq!(offsets(&limiter, "a.com", 5).await, vec![0, 0, 500, 1000, 1500]);
+
+        // another host has own bucket
+        assert_eq!(offsets(&limiter, "b.com", 2).await, vec![0, 0]);
+
+        // bucket refills while idle
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(offsets(&limiter, "a.com", 3).await, vec![0, 0, 500]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_min_delay() {
+        let limiter = HostRateLimiter::create(Some(RateLimit {
+            requests_per_second: 10.0,
+            burst: 3,
+            min_delay: Duration::from_millis(300),
+        }));
+        assert_eq!(offsets(&limiter, "a.com", 3).await, vec![0, 300, 600]);
+
+        limiter.raise_min_delay("b.com", Duration::from_secs(1));
+        limiter.raise_min_delay("b.com", Duration::from_millis(200));
+        assert_eq!(offsets(&limiter, "b.com", 3).await, vec![0, 1000, 2000]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrent_acquire() {
+        let limiter = HostRateLimiter::create(Some(RateLimit {
+            requests_per_second: 4.0,
+            burst: 1,
+            min_delay: Duration::ZERO,
+        }));
+        let begin = Instant::now();
+        let tasks = (0..4).map(|_| async {
+            limiter.acquire("a.com").await;
+            (Instant::now() - begin).as_millis()
+        });
+        let mut starts = futures::future::join_all(tasks).await;
+        starts.sort();
+        assert_eq!(starts, vec![0, 250, 500, 750]);
+
+        let crawling = HostRateLimiter::create(Some(RateLimit {
+            requests_per_second: 1e-300,
+            burst: 1,
+            min_delay: Duration::ZERO,
+        }));
+        assert_eq!(offsets(&crawling, "a.com", 2).await, vec![0, MAX_DELAY.as_millis()]);
+
+        let unlimited = HostRateLimiter::create(None);
+        assert_eq!(offsets(&unlimited, "a.com", 3).await, vec![0, 0, 0]);
+    }
+}
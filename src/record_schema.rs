@@ -0,0 +1,417 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use async_trait::async_trait;
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::{
+    etl_config_parser::SourceConfig,
+    record_sink::{CardRecord, RecordSink, RecordSinkError, FILTER_URL_KEY},
+    transform_html::{
+        defs::{OnNoMatch, ParserTransfromRule, MATCHED_SELECTORS_KEY},
+        value_type::{OnTypeError, ValueType},
+    },
+};
+
+/// Output of rules as the transform writes it, values of a path written twice are merged
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    /// JSON types of a value, `format` is set for dates
+    Scalar {
+        types: BTreeSet<&'static str>,
+        format: Option<&'static str>,
+    },
+    Object(BTreeMap<String, Shape>),
+    /// dict with any keys, like one of a dict `extract`
+    Map(Box<Shape>),
+    Array(Box<Shape>),
+}
+
+impl Shape {
+    fn scalar(types: &[&'static str], format: Option<&'static str>) -> Self {
+        Shape::Scalar {
+            types: types.iter().copied().collect(),
+            format,
+        }
+    }
+
+    fn with_null(self, nullable: bool) -> Self {
+        match self {
+            Shape::Scalar { mut types, format } if nullable => {
+                types.insert("null");
+                Shape::Scalar { types, format }
+            }
+            shape => shape,
+        }
+    }
+
+    fn merge(self, other: Shape) -> Shape {
+        match (self, other) {
+            (Shape::Scalar { types, format }, Shape::Scalar { types: other_types, format: other_format }) => Shape::Scalar {
+                types: types.union(&other_types).copied().collect(),
+                format: if format == other_format { format } else { None },
+            },
+            (Shape::Object(mut properties), Shape::Object(other)) => {
+                for (key, shape) in other {
+                    merge_property(&mut properties, key, shape);
+                }
+                Shape::Object(properties)
+            }
+            (Shape::Map(items), Shape::Map(other)) => Shape::Map(Box::new(items.merge(*other))),
+            (Shape::Array(items), Shape::Array(other)) => Shape::Array(Box::new(items.merge(*other))),
+            (_, other) => other,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            Shape::Scalar { types, format } => {
+                let mut schema = match types.len() {
+                    1 => json!({ "type": types.first() }),
+                    _ => json!({ "type": types }),
+                };
+                if let Some(format) = format {
+                    schema["format"] = json!(format);
+                }
+                schema
+            }
+            Shape::Object(properties) => json!({
+                "type": "object",
+                "properties": properties
+                    .iter()
+                    .map(|(key, shape)| (key.clone(), shape.to_json()))
+                    .collect::<serde_json::Map<_, _>>(),
+            }),
+            Shape::Map(items) => json!({ "type": "object", "additionalProperties": items.to_json() }),
+            Shape::Array(items) => json!({ "type": "array", "items": items.to_json() }),
+        }
+    }
+}
+
+fn merge_property(properties: &mut BTreeMap<String, Shape>, key: String, shape: Shape) {
+    let shape = match properties.remove(&key) {
+        Some(existing) => existing.merge(shape),
+        None => shape,
+    };
+    properties.insert(key, shape);
+}
+
+/// Properties of the object at the key, a value of another shape there is replaced
+fn object_at<'o>(properties: &'o mut BTreeMap<String, Shape>, key: &str, in_array: bool) -> &'o mut BTreeMap<String, Shape> {
+    let entry = properties
+        .entry(key.to_owned())
+        .or_insert_with(|| Shape::Object(BTreeMap::new()));
+    let fits = match entry {
+        Shape::Array(items) => in_array && matches!(**items, Shape::Object(_)),
+        Shape::Object(_) => !in_array,
+        _ => false,
+    };
+    if !fits {
+        let object = Shape::Object(BTreeMap::new());
+        *entry = if in_array { Shape::Array(Box::new(object)) } else { object };
+    }
+    match entry {
+        Shape::Array(items) => match items.as_mut() {
+            Shape::Object(properties) => properties,
+            _ => unreachable!(),
+        },
+        Shape::Object(properties) => properties,
+        _ => unreachable!(),
+    }
+}
+
+/// Dotted path like `push_value_path`
+fn set_path(properties: &mut BTreeMap<String, Shape>, path: &str, shape: Shape) {
+    let keys: Vec<&str> = path.trim_matches('.').split('.').collect();
+    let (last, parents) = keys.split_last().expect("split always returns one part");
+    let properties = parents
+        .iter()
+        .fold(properties, |properties, key| object_at(properties, key, false));
+    merge_property(properties, last.to_string(), shape);
+}
+
+fn value_type_shape(value_type: Option<ValueType>) -> Shape {
+    match value_type {
+        None => Shape::scalar(&["string"], None),
+        Some(ValueType::Int) => Shape::scalar(&["integer"], None),
+        Some(ValueType::Float | ValueType::Decimal) => Shape::scalar(&["number"], None),
+        Some(ValueType::Bool) => Shape::scalar(&["boolean"], None),
+        Some(ValueType::Date) => Shape::scalar(&["string"], Some("date")),
+    }
+}
+
+fn add_rules(properties: &mut BTreeMap<String, Shape>, rules: &[ParserTransfromRule]) {
+    for rule in rules {
+        add_rule(properties, rule);
+    }
+}
+
+fn add_rule(properties: &mut BTreeMap<String, Shape>, rule: &ParserTransfromRule) {
+    let properties = if rule.grouping.is_empty() {
+        properties
+    } else {
+        object_at(properties, &rule.grouping, true)
+    };
+    let value = value_type_shape(rule.value_type)
+        .with_null(rule.value_type.is_some() && rule.on_type_error == OnTypeError::Null);
+    if !rule.selectors.is_empty() || rule.default.is_some() {
+        let matched = Shape::Map(Box::new(Shape::scalar(&["string", "null"], None)));
+        merge_property(properties, MATCHED_SELECTORS_KEY.to_owned(), matched);
+    }
+    if !rule.mapping.is_empty() {
+        let item = if rule.extract.is_dict() {
+            Shape::Map(Box::new(value.clone()))
+        } else {
+            value.clone()
+        };
+        let shape = match &rule.pick {
+            Some(pick) if pick.is_list() => Shape::Array(Box::new(item)),
+            _ => item,
+        };
+        set_path(properties, &rule.mapping, shape);
+    }
+    for path in rule.captures.values() {
+        set_path(properties, path, value.clone().with_null(rule.on_no_match == OnNoMatch::Null));
+    }
+    add_rules(properties, &rule.children);
+}
+
+/// JSON Schema of a dict the rules write
+pub fn rules_schema(rules: &[ParserTransfromRule]) -> Value {
+    let mut properties = BTreeMap::new();
+    add_rules(&mut properties, rules);
+    Shape::Object(properties).to_json()
+}
+
+/// Draft 7 JSON Schema of card records of the source, the menu is in `definitions`
+pub fn source_schema(source: &SourceConfig) -> Value {
+    let mut card = rules_schema(&source.card.rules);
+    card["properties"][FILTER_URL_KEY] = json!({ "type": "string" });
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": source.name,
+        "definitions": {
+            "menu": rules_schema(&source.menu.rules),
+            "card": card,
+        },
+        "$ref": "#/definitions/card",
+    })
+}
+
+/// First problem of a record
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemaViolation {
+    /// JSON pointer like `/Price`
+    pub path: String,
+    pub message: String,
+}
+
+pub struct RecordValidator {
+    schema: JSONSchema,
+}
+
+impl RecordValidator {
+    pub fn compile(schema: &Value) -> Result<Self, RecordSinkError> {
+        let schema = JSONSchema::compile(schema).map_err(|err| RecordSinkError::SchemaError(err.to_string()))?;
+        Ok(Self { schema })
+    }
+
+    pub fn validate(&self, record: &Value) -> Result<(), SchemaViolation> {
+        let Err(mut errors) = self.schema.validate(record) else {
+            return Ok(());
+        };
+        let error = errors.next().expect("failed validation has an error");
+        Err(SchemaViolation {
+            path: error.instance_path.to_string(),
+            message: error.to_string(),
+        })
+    }
+}
+
+/// Passes records matching the schema to the inner sink, others are written to a JSON lines
+/// quarantine file with the failing path
+pub struct ValidatingSink<S: RecordSink> {
+    validator: RecordValidator,
+    inner: S,
+    quarantine_path: PathBuf,
+    quarantine: Mutex<BufWriter<File>>,
+    quarantined: AtomicUsize,
+}
+
+impl<S: RecordSink> ValidatingSink<S> {
+    pub fn create(schema: &Value, quarantine_path: &Path, inner: S) -> Result<Self, RecordSinkError> {
+        Ok(Self {
+            validator: RecordValidator::compile(schema)?,
+            inner,
+            quarantine_path: quarantine_path.to_owned(),
+            quarantine: Mutex::new(BufWriter::new(File::create(quarantine_path)?)),
+            quarantined: AtomicUsize::new(0),
+        })
+    }
+}
+
+#[async_trait]
+impl<S: RecordSink> RecordSink for ValidatingSink<S> {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+        let data = serde_json::to_value(record.tagged_data())?;
+        let Err(violation) = self.validator.validate(&data) else {
+            return self.inner.consume(record).await;
+        };
+        warn!(
+            "record of [{}] page [{}] is quarantined, [{}] {}",
+            record.filter_url, record.page_number, violation.path, violation.message
+        );
+        let mut line = serde_json::to_vec(&json!({
+            "path": violation.path,
+            "message": violation.message,
+            "page_number": record.page_number,
+            "record": data,
+        }))?;
+        line.push(b'\n');
+        self.quarantine.lock().unwrap().write_all(&line)?;
+        self.quarantined.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn page_finished(&self, filter_url: &str, page_number: usize) -> Result<(), RecordSinkError> {
+        self.inner.page_finished(filter_url, page_number).await
+    }
+
+    async fn finish(&self) -> Result<(), RecordSinkError> {
+        self.quarantine.lock().unwrap().flush()?;
+        let quarantined = self.quarantined.load(Ordering::Relaxed);
+        if quarantined > 0 {
+            info!("[{quarantined}] records are quarantined to {}", self.quarantine_path.display());
+        }
+        self.inner.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        page_walker::PageWalker,
+        record_sink::MemorySink,
+        transform_html::defs::{TransformedData, DataMap},
+    };
+
+    use super::*;
+
+    fn card(data: &[(&str, TransformedData)]) -> CardRecord {
+        CardRecord {
+            filter_url: "/search".into(),
+            page_number: 1,
+            data: Box::new(data.iter().cloned().map(|(key, value)| (key.to_owned(), value)).collect()),
+        }
+    }
+
+    #[test]
+    fn test_rules_schema() {
+        let rules: Vec<ParserTransfromRule> = serde_yaml::from_str(
+            r#"
+- selector: h1
+  mapping: Title
+- selector: span.price
+  mapping: Price
+  type: int
+- selector: li
+  mapping: Tags
+  pick: unique
+- selector: p.address
+  mapping: Address
+  regex: '(?P<city>\w+)$'
+  captures: { city: Location.City }
+  on_no_match: 'null'
+- selector: article
+  grouping: Items
+  children:
+    - selector: a
+      selectors: [a.link]
+      mapping: url
+      attribute_name: href
+    - selector: img
+      mapping: Images
+      extract: srcset
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rules_schema(&rules),
+            json!({
+                "type": "object",
+                "properties": {
+                    "Address": { "type": "string" },
+                    "Items": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "Images": { "type": "object", "additionalProperties": { "type": "string" } },
+                                "_matched_selectors": {
+                                    "type": "object",
+                                    "additionalProperties": { "type": ["null", "string"] },
+                                },
+                                "url": { "type": "string" },
+                            },
+                        },
+                    },
+                    "Location": {
+                        "type": "object",
+                        "properties": { "City": { "type": ["null", "string"] } },
+                    },
+                    "Price": { "type": ["integer", "null"] },
+                    "Tags": { "type": "array", "items": { "type": "string" } },
+                    "Title": { "type": "string" },
+                },
+            })
+        );
+
+        let config = PageWalker::parse_config(&PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("etl-config.yaml")).unwrap();
+        for source in config.sources.iter() {
+            let schema = source_schema(source);
+            assert_eq!(schema["title"], json!(source.name));
+            assert!(RecordValidator::compile(&schema).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validating_sink() -> Result<(), RecordSinkError> {
+        let schema = json!({
+            "$ref": "#/definitions/card",
+            "definitions": { "card": rules_schema(&serde_yaml::from_str::<Vec<ParserTransfromRule>>(
+                "[{ selector: span, mapping: Price, type: int, on_type_error: error }, { selector: h1, mapping: Title }]"
+            ).unwrap()) },
+        });
+        let quarantine_path = std::env::temp_dir().join(format!("test_validating_sink_{}.jsonl", std::process::id()));
+        let sink = ValidatingSink::create(&schema, &quarantine_path, MemorySink::default())?;
+        sink.consume(card(&[("Price", TransformedData::Int(5)), ("Title", "Villa".into())])).await?;
+        sink.consume(card(&[("Price", "5 AED".into())])).await?;
+        sink.consume(card(&[("Title", TransformedData::create_dict())])).await?;
+        sink.finish().await?;
+
+        let pages = sink.inner.into_pages();
+        let valid: &Vec<DataMap> = &pages[&("/search".to_owned(), 1)];
+        assert_eq!(valid.len(), 1);
+        let quarantined: Vec<Value> = fs::read_to_string(&quarantine_path)?
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(quarantined.len(), 2);
+        assert_eq!(quarantined[0]["path"], json!("/Price"));
+        assert_eq!(quarantined[0]["record"][FILTER_URL_KEY], json!("/search"));
+        assert_eq!(quarantined[1]["path"], json!("/Title"));
+        Ok(())
+    }
+}
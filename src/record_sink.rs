@@ -0,0 +1,239 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use async_trait::async_trait;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::transform_html::defs::DataMap;
+
+/// Key of the filter tag in written card records
+pub const FILTER_URL_KEY: &str = "filter_url";
+
+/// Card data extracted from a single card page
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CardRecord {
+    pub filter_url: String,
+    pub page_number: usize,
+    pub data: DataMap,
+}
+
+impl CardRecord {
+    /// Card data with the filter it came from
+    pub fn tagged_data(&self) -> DataMap {
+        let mut data = self.data.clone();
+        data.insert(FILTER_URL_KEY.into(), self.filter_url.as_str().into());
+        data
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum RecordSinkError {
+    #[error("couldn't write records {0}")]
+    FileError(#[from] std::io::Error),
+    #[error("couldn't serialize record {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("couldn't send records {0}")]
+    ReqwestError(#[from] reqwest::Error),
+    #[error("clickhouse responded with status [{0}] {1}")]
+    ClickhouseStatus(u16, String),
+    #[error("couldn't compile record schema {0}")]
+    SchemaError(String),
+}
+
+/// Receives every card record as soon as it is extracted
+#[async_trait]
+pub trait RecordSink: Send + Sync {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError>;
+
+    /// Called once a menu page is done, including pages without cards
+    async fn page_finished(
+        &self,
+        _filter_url: &str,
+        _page_number: usize,
+    ) -> Result<(), RecordSinkError> {
+        Ok(())
+    }
+
+    /// Called once after the last page, sinks flush and publish results here
+    async fn finish(&self) -> Result<(), RecordSinkError> {
+        Ok(())
+    }
+}
+
+type PageKey = (String, usize);
+
+/// Keeps records in memory, grouped by filter url and page number
+#[derive(Debug, Default)]
+#[allow(dead_code)]
+pub struct MemorySink {
+    pages: dashmap::DashMap<PageKey, Vec<DataMap>>,
+}
+
+#[allow(dead_code)]
+impl MemorySink {
+    pub fn into_pages(self) -> BTreeMap<PageKey, Vec<DataMap>> {
+        self.pages.into_iter().collect()
+    }
+}
+
+#[async_trait]
+impl RecordSink for MemorySink {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+        self.pages
+            .entry((record.filter_url, record.page_number))
+            .or_default()
+            .push(record.data);
+        Ok(())
+    }
+
+    async fn page_finished(
+        &self,
+        filter_url: &str,
+        page_number: usize,
+    ) -> Result<(), RecordSinkError> {
+        self.pages
+            .entry((filter_url.to_owned(), page_number))
+            .or_default();
+        Ok(())
+    }
+}
+
+/// Prints one card record per line
+#[derive(Debug, Default)]
+pub struct StdoutSink;
+
+#[async_trait]
+impl RecordSink for StdoutSink {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+        let mut line = serde_json::to_vec(&record.tagged_data())?;
+        line.push(b'\n');
+        io::stdout().lock().write_all(&line)?;
+        Ok(())
+    }
+
+    async fn finish(&self) -> Result<(), RecordSinkError> {
+        io::stdout().lock().flush()?;
+        Ok(())
+    }
+}
+
+/// Feeds every record to all sinks in order
+#[derive(Default)]
+pub struct SinkChain {
+    sinks: Vec<Box<dyn RecordSink>>,
+}
+
+impl SinkChain {
+    pub fn with(mut self, sink: impl RecordSink + 'static) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+}
+
+#[async_trait]
+impl RecordSink for SinkChain {
+    async fn consume(&self, record: CardRecord) -> Result<(), RecordSinkError> {
+        for sink in self.sinks.iter() {
+            sink.consume(record.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn page_finished(
+        &self,
+        filter_url: &str,
+        page_number: usize,
+    ) -> Result<(), RecordSinkError> {
+        for sink in self.sinks.iter() {
+            sink.page_finished(filter_url, page_number).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&self) -> Result<(), RecordSinkError> {
+        for sink in self.sinks.iter() {
+            sink.finish().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use crate::transform_html::defs::TransformedData;
+
+    use super::*;
+
+    fn card(page_number: usize, id: &str) -> CardRecord {
+        let mut data = TransformedData::create_data_map();
+        data.insert("ID".into(), id.into());
+        CardRecord {
+            filter_url: "/search".into(),
+            page_number,
+            data,
+        }
+    }
+
+    struct CountingSink(Arc<Mutex<(usize, usize, usize)>>);
+
+    #[async_trait]
+    impl RecordSink for CountingSink {
+        async fn consume(&self, _record: CardRecord) -> Result<(), RecordSinkError> {
+            self.0.lock().unwrap().0 += 1;
+            Ok(())
+        }
+        async fn page_finished(
+            &self,
+            _filter_url: &str,
+            _page_number: usize,
+        ) -> Result<(), RecordSinkError> {
+            self.0.lock().unwrap().1 += 1;
+            Ok(())
+        }
+        async fn finish(&self) -> Result<(), RecordSinkError> {
+            self.0.lock().unwrap().2 += 1;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sink_chain() -> Result<(), RecordSinkError> {
+        let counters = Arc::new(Mutex::new((0, 0, 0)));
+        let chain = SinkChain::default()
+            .with(CountingSink(counters.clone()))
+            .with(CountingSink(counters.clone()));
+
+        chain.consume(card(1, "1")).await?;
+        chain.consume(card(1, "2")).await?;
+        chain.page_finished("/search", 1).await?;
+        chain.page_finished("/search", 2).await?;
+        chain.finish().await?;
+
+        assert_eq!(*counters.lock().unwrap(), (4, 4, 2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_sink() -> Result<(), RecordSinkError> {
+        let sink = MemorySink::default();
+        sink.consume(card(2, "3")).await?;
+        sink.consume(card(1, "1")).await?;
+        sink.consume(card(1, "2")).await?;
+        sink.page_finished("/search", 3).await?;
+        sink.page_finished("/other", 1).await?;
+
+        let pages = sink.into_pages();
+        let page = |filter_url: &str, page_number: usize| &pages[&(filter_url.into(), page_number)];
+        assert_eq!(pages.len(), 4);
+        assert_eq!(*page("/search", 1), vec![card(1, "1").data, card(1, "2").data]);
+        assert!(page("/search", 3).is_empty());
+        assert!(page("/other", 1).is_empty());
+        assert_eq!(card(1, "1").tagged_data()[FILTER_URL_KEY], "/search".into());
+        Ok(())
+    }
+}
@@ -6,6 +6,8 @@ use tracing_subscriber::field::display;
 use std::{any::TypeId, collections::HashMap, future::Future, iter::Take, str::FromStr, time::Duration};
 use thiserror::Error;
 
+use crate::rate_limiter::{HostRateLimiter, RateLimit};
+
 type HeadersMap = HashMap<String, String>;
 type StatusVec = Vec<u16>;
 
@@ -26,6 +28,8 @@ pub struct RequestMakerConfig {
 
     pub headers: HeadersMap,
     pub status_forcelist: StatusVec,
+    /// per host pacing, every attempt of a retry is paced as well
+    pub rate_limit: Option<RateLimit>,
 }
 
 #[derive(Debug)]
@@ -33,6 +37,7 @@ pub struct RequestMaker {
     client: reqwest::Client,
     pub config: RequestMakerConfig,
     retry_strategy:  Take<ExponentialBackoff>,
+    rate_limiter: HostRateLimiter,
 }
 
 impl Default for RequestMakerConfig {
@@ -47,6 +52,7 @@ impl Default for RequestMakerConfig {
                     .into(),
             )]),
             status_forcelist: vec![500, 502, 503, 504],
+            rate_limit: None,
         }
     }
 }
@@ -64,17 +70,14 @@ pub enum RequestMakerError {
     StatusCodeError(#[from] StatusCodeError),
     InvalidMethod(#[from] http::method::InvalidMethod),
     MiddlewareError(#[from] reqwest_middleware::Error),
+    InvalidHeaderName(#[from] reqwest::header::InvalidHeaderName),
+    InvalidHeaderValue(#[from] reqwest::header::InvalidHeaderValue),
 }
 
-fn from(hashmap: &HeadersMap) -> reqwest::header::HeaderMap {
+fn from(hashmap: &HeadersMap) -> Result<reqwest::header::HeaderMap, RequestMakerError> {
     hashmap
         .iter()
-        .map(|(k, v)| {
-            (
-                HeaderName::from_str(k).unwrap(),
-                HeaderValue::from_str(v).unwrap(),
-            )
-        })
+        .map(|(k, v)| Ok((HeaderName::from_str(k)?, HeaderValue::from_str(v)?)))
         .collect()
 }
 
@@ -238,7 +241,7 @@ mod middleware {
 impl RequestMaker {
     pub fn create(config: RequestMakerConfig) -> Result<Self, RequestMakerError> {
         let client = reqwest::Client::builder()
-            .default_headers(from(&config.headers))
+            .default_headers(from(&config.headers)?)
             .pool_idle_timeout(config.timeout)
             .timeout(config.timeout)
             .build()?;
@@ -247,7 +250,9 @@ impl RequestMaker {
             .factor(config.backoff_factor.into())
             .take(config.max_retries.try_into().expect("unexpected max_retries value"));
 
-        Ok(Self { client, config, retry_strategy })
+        let rate_limiter = HostRateLimiter::create(config.rate_limit.clone());
+
+        Ok(Self { client, config, retry_strategy, rate_limiter })
     }
 
     
@@ -259,16 +264,18 @@ impl RequestMaker {
         let method = reqwest::Method::from_bytes(params.method.to_uppercase().as_bytes())?;
         let mut req = self.client.request(method, params.url.as_str());
         for (k, v) in params.headers.iter() {
-            req = req.header(
-                HeaderName::from_str(k).unwrap(),
-                HeaderValue::from_str(v).unwrap(),
-            );
+            req = req.header(HeaderName::from_str(k)?, HeaderValue::from_str(v)?);
         }
 
         let status_forcelist_ref: &StatusVec =  if let Some(ref svec) = params.status_forcelist { svec }
                                                 else { &self.config.status_forcelist };
+        let host = reqwest::Url::parse(&params.url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
         let resp = Retry::spawn(self.retry_strategy.clone(),  || async {
             
+            self.rate_limiter.acquire(&host).await;
             let status_forcelist = status_forcelist_ref.clone();
             let req = req.try_clone().expect("somthing wrong with request object");
             let future = req.send();
@@ -289,6 +296,11 @@ impl RequestMaker {
         resp
     }
 
+    /// Slows down every later request to the host
+    pub fn raise_min_delay(&self, host: &str, min_delay: Duration) {
+        self.rate_limiter.raise_min_delay(host, min_delay);
+    }
+
     pub async fn request_text(
         &self,
         params: &RequestParams,
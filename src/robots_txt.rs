@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::OnceCell;
+use tracing::{info, warn};
+
+use crate::{
+    rate_limiter::MAX_DELAY,
+    request_maker::{RequestMaker, RequestParams},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+struct RobotsRule {
+    allow: bool,
+    pattern: String,
+}
+
+/// Rules of the group matched by our user agent, see RFC 9309
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RobotsTxt {
+    rules: Vec<RobotsRule>,
+    disallow_all: bool,
+    pub crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    agents: Vec<String>,
+    rules: Vec<RobotsRule>,
+    crawl_delay: Option<Duration>,
+}
+
+impl RobotsTxt {
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Used when robots.txt is unreachable
+    pub fn disallow_all() -> Self {
+        Self {
+            disallow_all: true,
+            ..Default::default()
+        }
+    }
+
+    /// Picks the group whose agent token is the longest part of `user_agent`, falls back to `*`
+    pub fn parse(text: &str, user_agent: &str) -> Self {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut collecting_agents = false;
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let (key, value) = (key.trim().to_lowercase(), value.trim());
+            match key.as_str() {
+                "user-agent" => {
+                    if !collecting_agents {
+                        groups.push(Group::default());
+                        collecting_agents = true;
+                    }
+                    groups.last_mut().unwrap().agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" => {
+                    collecting_agents = false;
+                    let Some(group) = groups.last_mut() else { continue };
+                    // empty disallow allows everything
+                    if !value.is_empty() {
+                        group.rules.push(RobotsRule {
+                            allow: key == "allow",
+                            pattern: value.to_owned(),
+                        });
+                    }
+                }
+                "crawl-delay" => {
+                    collecting_agents = false;
+                    let Some(group) = groups.last_mut() else { continue };
+                    // `1e300` doesn't fit a Duration
+                    group.crawl_delay = value.parse::<f64>().ok().filter(|delay| *delay >= 0.0).map(|delay| {
+                        Duration::try_from_secs_f64(delay).map_or(MAX_DELAY, |delay| delay.min(MAX_DELAY))
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let user_agent = user_agent.to_lowercase();
+        let matched_len = |group: &Group| {
+            group
+                .agents
+                .iter()
+                .filter(|agent| *agent != "*" && user_agent.contains(agent.as_str()))
+                .map(|agent| agent.len())
+                .max()
+        };
+        let best_len = groups.iter().filter_map(matched_len).max();
+        let selected: Vec<&Group> = match best_len {
+            Some(len) => groups.iter().filter(|g| matched_len(g) == Some(len)).collect(),
+            None => groups
+                .iter()
+                .filter(|g| g.agents.iter().any(|agent| agent == "*"))
+                .collect(),
+        };
+
+        Self {
+            rules: selected.iter().flat_map(|g| g.rules.clone()).collect(),
+            disallow_all: false,
+            crawl_delay: selected.iter().find_map(|g| g.crawl_delay),
+        }
+    }
+
+    /// Longest matching rule wins, allow wins a tie. `path` includes the query
+    pub fn is_allowed(&self, path: &str) -> bool {
+        if self.disallow_all {
+            return false;
+        }
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map(|rule| rule.allow)
+            .unwrap_or(true)
+    }
+}
+
+/// Prefix match with `*` wildcards and `$` end anchor
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(pattern) => (pattern, true),
+        None => (pattern, false),
+    };
+    let parts: Vec<&str> = pattern.split('*').collect();
+    let Some(rest) = path.strip_prefix(parts[0]) else {
+        return false;
+    };
+    if parts.len() == 1 {
+        return !anchored || rest.is_empty();
+    }
+    let mut positions = vec![0];
+    for (idx, part) in parts[1..].iter().enumerate() {
+        let is_last = idx == parts.len() - 2;
+        let mut next = Vec::new();
+        for &pos in positions.iter() {
+            if is_last && anchored {
+                if rest.len() >= pos + part.len() && rest.ends_with(part) {
+                    return true;
+                }
+                continue;
+            }
+            let mut from = pos;
+            while let Some(found) = rest[from..].find(part) {
+                next.push(from + found + part.len());
+                if part.is_empty() {
+                    break;
+                }
+                from += found + 1;
+            }
+        }
+        if next.is_empty() {
+            return false;
+        }
+        next.sort();
+        next.dedup();
+        positions = next;
+    }
+    !anchored
+}
+
+/// robots.txt per origin, fetched once on the first url of the origin
+#[derive(Debug)]
+pub struct RobotsCache {
+    user_agent: String,
+    origins: Mutex<HashMap<String, Arc<OnceCell<Arc<RobotsTxt>>>>>,
+    skipped_urls: Mutex<Vec<String>>,
+}
+
+impl RobotsCache {
+    pub fn create(user_agent: String) -> Self {
+        Self {
+            user_agent,
+            origins: Mutex::new(HashMap::new()),
+            skipped_urls: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 4xx means no restrictions, other failures disallow the whole origin (RFC 9309, 2.3.1)
+    async fn fetch(&self, request_maker: &RequestMaker, origin: &str, host: &str) -> RobotsTxt {
+        let url = format!("{origin}/robots.txt");
+        info!("read robots [{url}]");
+        let response = request_maker
+            .request(&RequestParams {
+                method: "GET".to_owned(),
+                url: url.clone(),
+                ..Default::default()
+            })
+            .await;
+        let robots = match response {
+            Ok(resp) if resp.status().is_success() => match resp.text().await {
+                Ok(text) => RobotsTxt::parse(&text, &self.user_agent),
+                Err(err) => {
+                    warn!("couldn't read [{url}], origin is disallowed: {err:?}");
+                    RobotsTxt::disallow_all()
+                }
+            },
+            Ok(resp) if resp.status().is_client_error() => RobotsTxt::allow_all(),
+            Ok(resp) => {
+                warn!("status [{}] of [{url}], origin is disallowed", resp.status());
+                RobotsTxt::disallow_all()
+            }
+            Err(err) => {
+                warn!("couldn't fetch [{url}], origin is disallowed: {err:?}");
+                RobotsTxt::disallow_all()
+            }
+        };
+        if let Some(crawl_delay) = robots.crawl_delay {
+            request_maker.raise_min_delay(host, crawl_delay);
+        }
+        robots
+    }
+
+    /// Disallowed urls are kept for `skipped_urls`
+    pub async fn is_allowed(&self, request_maker: &RequestMaker, url: &str) -> bool {
+        let Ok(parsed) = reqwest::Url::parse(url) else {
+            return true;
+        };
+        let origin = parsed.origin().ascii_serialization();
+        let host = parsed.host_str().unwrap_or_default();
+        let cell = self
+            .origins
+            .lock()
+            .unwrap()
+            .entry(origin.clone())
+            .or_default()
+            .clone();
+        let robots = cell
+            .get_or_init(|| async { Arc::new(self.fetch(request_maker, &origin, host).await) })
+            .await;
+
+        let path = match parsed.query() {
+            Some(query) => format!("{}?{query}", parsed.path()),
+            None => parsed.path().to_owned(),
+        };
+        let allowed = robots.is_allowed(&path);
+        if !allowed {
+            self.skipped_urls.lock().unwrap().push(url.to_owned());
+        }
+        allowed
+    }
+
+    pub fn skipped_urls(&self) -> Vec<String> {
+        self.skipped_urls.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS: &str = r#"
+# comment
+User-agent: *
+Disallow: /private
+Allow: /private/open
+Disallow: /*.pdf$
+Disallow: /search?*sort=
+Crawl-delay: 2
+
+User-agent: scraperbot
+User-agent: otherbot
+Disallow: /
+Allow: /en/
+Crawl-delay: 0.5
+"#;
+
+    #[test]
+    fn test_default_group() {
+        let robots = RobotsTxt::parse(ROBOTS, "Mozilla/5.0 Firefox/113.0");
+        assert_eq!(robots.crawl_delay, Some(Duration::from_secs(2)));
+        assert!(robots.is_allowed("/en/search"));
+        assert!(!robots.is_allowed("/private/data"));
+        assert!(robots.is_allowed("/private/open/data"));
+        assert!(!robots.is_allowed("/files/a.pdf"));
+        assert!(robots.is_allowed("/files/a.pdf?download=1"));
+        assert!(!robots.is_allowed("/search?c=1&sort=price"));
+        assert!(robots.is_allowed("/search?c=1"));
+    }
+
+    #[test]
+    fn test_agent_group() {
+        let robots = RobotsTxt::parse(ROBOTS, "Mozilla/5.0 (compatible; OtherBot/1.0)");
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(500)));
+        assert!(!robots.is_allowed("/for-sale/property"));
+        assert!(robots.is_allowed("/en/search"));
+        assert!(robots.is_allowed("/robots.txt"));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let robots = RobotsTxt::parse("User-agent: *\nDisallow:\n", "bot");
+        assert!(robots.is_allowed("/anything"));
+        assert_eq!(RobotsTxt::parse("", "bot"), RobotsTxt::allow_all());
+        assert!(!RobotsTxt::disallow_all().is_allowed("/"));
+
+        let crawl_delay = |value: &str| RobotsTxt::parse(&format!("User-agent: *\nCrawl-delay: {value}\n"), "bot").crawl_delay;
+        assert_eq!(crawl_delay("1e300"), Some(MAX_DELAY));
+        assert_eq!(crawl_delay("inf"), Some(MAX_DELAY));
+        assert_eq!(crawl_delay("3600"), Some(MAX_DELAY));
+        assert_eq!(crawl_delay("NaN"), None);
+        assert_eq!(crawl_delay("-1"), None);
+
+        assert!(pattern_matches("/a*b*c", "/a-b-c-d"));
+        assert!(!pattern_matches("/a*b*c$", "/a-b-c-d"));
+        assert!(pattern_matches("/a*c$", "/a-c-c"));
+        assert!(pattern_matches("*", "/"));
+        assert!(!pattern_matches("/b", "/a/b"));
+    }
+}
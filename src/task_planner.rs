@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+
+use crate::page_walker::FilterRange;
+
+/// Page range of one filter, crawled by a single orchestrator task
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CrawlTask {
+    pub task_id: usize,
+    pub source_name: String,
+    pub filter_url: String,
+    pub begin_page: usize,
+    pub end_page: usize,
+}
+
+impl CrawlTask {
+    pub fn filter_range(&self) -> FilterRange {
+        FilterRange {
+            filter_url: self.filter_url.clone(),
+            begin_page: self.begin_page,
+            end_page: self.end_page,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct TaskManifest {
+    pub source_name: String,
+    pub menu_pages_per_task: Option<usize>,
+    pub tasks: Vec<CrawlTask>,
+}
+
+/// Splits every filter range into tasks of `menu_pages_per_task` pages,
+/// ranges stay whole without the limit. Task ids follow filter and page order
+pub fn plan_tasks(
+    source_name: &str,
+    filters: &[FilterRange],
+    menu_pages_per_task: Option<usize>,
+) -> TaskManifest {
+    let mut tasks = Vec::new();
+    for filter in filters {
+        let step = menu_pages_per_task
+            .filter(|step| *step > 0)
+            .unwrap_or(usize::MAX);
+        let mut begin_page = filter.begin_page;
+        while begin_page <= filter.end_page {
+            let end_page = begin_page.saturating_add(step - 1).min(filter.end_page);
+            tasks.push(CrawlTask {
+                task_id: tasks.len(),
+                source_name: source_name.to_owned(),
+                filter_url: filter.filter_url.clone(),
+                begin_page,
+                end_page,
+            });
+            begin_page = end_page + 1;
+        }
+    }
+    TaskManifest {
+        source_name: source_name.to_owned(),
+        menu_pages_per_task,
+        tasks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(filter_url: &str, begin_page: usize, end_page: usize) -> FilterRange {
+        FilterRange {
+            filter_url: filter_url.into(),
+            begin_page,
+            end_page,
+        }
+    }
+
+    fn pages(manifest: &TaskManifest) -> Vec<(usize, &str, usize, usize)> {
+        manifest
+            .tasks
+            .iter()
+            .map(|t| (t.task_id, t.filter_url.as_str(), t.begin_page, t.end_page))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan_tasks() {
+        let manifest = plan_tasks(
+            "propertyfinder",
+            &[range("/a", 1, 40), range("/b", 3, 5), range("/c", 2, 1)],
+            Some(15),
+        );
+        assert_eq!(
+            pages(&manifest),
+            vec![
+                (0, "/a", 1, 15),
+                (1, "/a", 16, 30),
+                (2, "/a", 31, 40),
+                (3, "/b", 3, 5),
+            ]
+        );
+        assert_eq!(manifest.tasks[1].filter_range(), range("/a", 16, 30));
+
+        let manifest = plan_tasks("bayut", &[range("/a", 1, 40)], None);
+        assert_eq!(pages(&manifest), vec![(0, "/a", 1, 40)]);
+
+        let json = serde_json::to_string(&plan_tasks("bayut", &[range("/a", 1, 1)], Some(50))).unwrap();
+        assert_eq!(
+            json,
+            r#"{"source_name":"bayut","menu_pages_per_task":50,"tasks":[{"task_id":0,"source_name":"bayut","filter_url":"/a","begin_page":1,"end_page":1}]}"#
+        );
+    }
+}
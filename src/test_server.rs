@@ -0,0 +1,158 @@
+//! Local HTTP stub for tests, stands in for scraped sites and HTTP APIs
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+};
+
+#[derive(Debug, Clone)]
+pub struct TestRequest {
+    pub method: String,
+    /// path with query
+    pub path: String,
+    pub body: String,
+}
+
+type Handler = dyn Fn(&TestRequest) -> (u16, String) + Send + Sync;
+
+#[derive(Default)]
+struct ServerState {
+    requests: Mutex<Vec<TestRequest>>,
+    delay: Mutex<Duration>,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    state: Arc<ServerState>,
+    handle: JoinHandle<()>,
+}
+
+impl TestServer {
+    pub async fn start(
+        handler: impl Fn(&TestRequest) -> (u16, String) + Send + Sync + 'static,
+    ) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(ServerState::default());
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let state_ref = state.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let (state, handler) = (state_ref.clone(), handler.clone());
+                tokio::spawn(async move {
+                    let _ = serve(stream, state, handler).await;
+                });
+            }
+        });
+        TestServer {
+            addr,
+            state,
+            handle,
+        }
+    }
+
+    /// Serves html pages by exact path, other paths get 404
+    pub async fn with_pages(pages: Vec<(String, String)>) -> TestServer {
+        TestServer::start(move |req| {
+            pages
+                .iter()
+                .find(|(path, _)| *path == req.path)
+                .map(|(_, body)| (200, body.clone()))
+                .unwrap_or((404, String::new()))
+        })
+        .await
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    pub fn requests(&self) -> Vec<TestRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Every response is sent after the delay
+    pub fn set_delay(&self, delay: Duration) {
+        *self.state.delay.lock().unwrap() = delay;
+    }
+
+    /// Max amount of requests handled at once
+    pub fn max_in_flight(&self) -> usize {
+        self.state.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn serve(
+    mut stream: TcpStream,
+    state: Arc<ServerState>,
+    handler: Arc<Handler>,
+) -> std::io::Result<()> {
+    loop {
+        let mut buf = Vec::new();
+        let header_end = loop {
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let mut chunk = [0u8; 4096];
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        };
+        let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+        while buf.len() < header_end + content_length {
+            let mut chunk = [0u8; 4096];
+            let read = stream.read(&mut chunk).await?;
+            if read == 0 {
+                break;
+            }
+            buf.extend_from_slice(&chunk[..read]);
+        }
+
+        let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+        let request = TestRequest {
+            method: request_line.next().unwrap_or("").to_owned(),
+            path: request_line.next().unwrap_or("").to_owned(),
+            body: String::from_utf8_lossy(&buf[header_end..]).to_string(),
+        };
+        state.requests.lock().unwrap().push(request.clone());
+
+        let in_flight = state.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        state.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        let delay = *state.delay.lock().unwrap();
+        tokio::time::sleep(delay).await;
+        let (status, body) = handler(&request);
+        state.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let response = format!(
+            "HTTP/1.1 {status} STUB\r\ncontent-type: text/html; charset=utf-8\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).await?;
+    }
+}
@@ -0,0 +1,81 @@
+//! Stub source and walker for tests against `TestServer`
+
+use std::fs;
+
+use crate::{
+    etl_config_parser::{EtlConfig, SourceConfig},
+    page_walker::PageWalker,
+    transform_html::defs::ParserTransfromRule,
+};
+
+/// `stub` source, `a.card` links of a menu page are its cards and `h1` of a card is `Title`
+const TEST_CONFIG: &str = r#"
+http:
+  retries:
+    max_retries: 1
+    backoff_factor: 2
+    status_forcelist: [ 500, 502, 503, 504 ]
+    timeout: 5
+  headers:
+    user-agent: 'test'
+sources:
+  - name: stub
+    root_url: ''
+    menu:
+      page_limit: 100
+      default_url: /search
+      page_url_sub: '?page=\1'
+      first_page_url: '?page=1'
+      rules:
+        - grouping: menu_items
+          selector: a.card
+          children:
+            - mapping: url
+              attribute_name: href
+    card:
+      rules:
+        - selector: h1
+          mapping: Title
+"#;
+
+fn test_config(root_url: &str) -> EtlConfig {
+    let mut config: EtlConfig = serde_yaml::from_str(TEST_CONFIG).unwrap();
+    config.sources[0].root_url = root_url.to_owned();
+    config
+}
+
+/// Walker of the `stub` source changed by `with_source`, the config is written to `{name}.yaml`
+pub fn create_test_walker_with(
+    root_url: &str,
+    name: &str,
+    with_source: impl FnOnce(&mut SourceConfig),
+) -> PageWalker {
+    let mut config = test_config(root_url);
+    with_source(&mut config.sources[0]);
+    let dir = std::env::temp_dir().join(format!("page_walker_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(format!("{name}.yaml"));
+    fs::write(&path, serde_yaml::to_string(&config).unwrap()).unwrap();
+    PageWalker::create("stub".into(), &path, 100).unwrap()
+}
+
+pub fn create_test_walker(root_url: &str, name: &str) -> PageWalker {
+    create_test_walker_with(root_url, name, |_| ())
+}
+
+/// `cards_limit` of a menu page from `span.count`
+pub fn cards_limit_rule() -> ParserTransfromRule {
+    ParserTransfromRule {
+        selector: "span.count".into(),
+        mapping: "cards_limit".into(),
+        ..Default::default()
+    }
+}
+
+pub fn menu_html(cards: &[&str]) -> String {
+    cards
+        .iter()
+        .map(|card| format!(r#"<a class="card" href="{card}">card</a>"#))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,435 @@
+use chrono::format::{Item, StrftimeItems};
+use regex::Regex;
+use scraper::{ElementRef, Selector};
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+use super::defs::{
+    prepare_rx_for_rust, prepare_rx_sub_for_replace, OnNoMatch, ParseAs, ParserTransfromRule, SelectorType,
+    TransformError, TransformedData,
+};
+use super::extract_target::ExtractTarget;
+use super::pick::Pick;
+use super::soup_selector::SoupSelector;
+use super::text_normalization::TextNormalizationOverride;
+use super::value_filter::{compile_filters, CompiledFilter};
+use super::value_type::{OnTypeError, ValueType};
+use super::xpath_selector::XPathSelector;
+
+/// Parsed `selector` of a rule
+#[derive(Debug, Clone)]
+pub enum CompiledSelector {
+    Empty,
+    Css(Selector),
+    /// with BeautifulSoup `:-soup-contains`
+    Soup(SoupSelector),
+    XPath(XPathSelector),
+    JsonPath(JsonPath),
+}
+
+impl CompiledSelector {
+    pub fn parse(selector: &str, selector_type: SelectorType) -> Result<Self, TransformError> {
+        if selector.is_empty() {
+            return Ok(CompiledSelector::Empty);
+        }
+        match selector_type {
+            SelectorType::Xpath => return Ok(CompiledSelector::XPath(XPathSelector::parse(selector)?)),
+            SelectorType::Jsonpath => {
+                return JsonPath::parse(selector)
+                    .map(CompiledSelector::JsonPath)
+                    .map_err(|err| TransformError::SelectorError {
+                        selector: selector.to_owned(),
+                        message: err.to_string(),
+                    })
+            }
+            SelectorType::Css => {}
+        }
+        if SoupSelector::has_contains(selector) {
+            return Ok(CompiledSelector::Soup(SoupSelector::parse(selector)?));
+        }
+        Selector::parse(selector)
+            .map(CompiledSelector::Css)
+            .map_err(|err| TransformError::SelectorError {
+                selector: selector.to_owned(),
+                message: format!("{err:?}"),
+            })
+    }
+
+    /// Matching descendants, None for the empty selector
+    pub fn select<'a>(&self, soup: &ElementRef<'a>) -> Option<Vec<ElementRef<'a>>> {
+        match self {
+            CompiledSelector::Empty => None,
+            CompiledSelector::Css(selector) => Some(soup.select(selector).collect()),
+            CompiledSelector::Soup(selector) => Some(selector.select(*soup)),
+            CompiledSelector::XPath(selector) => Some(selector.select(*soup)),
+            CompiledSelector::JsonPath(_) => Some(Vec::new()),
+        }
+    }
+
+    /// Matching JSON nodes, None for the empty selector
+    pub fn select_json<'v>(&self, json: &'v Value) -> Option<Vec<&'v Value>> {
+        match self {
+            CompiledSelector::Empty => None,
+            CompiledSelector::JsonPath(path) => Some(path.query(json).all()),
+            _ => Some(Vec::new()),
+        }
+    }
+}
+
+/// `regex_sub_value` with the Python replacement converted for Rust
+#[derive(Debug, Clone)]
+pub struct CompiledRegexSub {
+    pub regex: Regex,
+    pub replacement: String,
+}
+
+impl CompiledRegexSub {
+    pub fn replace_all(&self, text: &str) -> String {
+        self.regex.replace_all(text, self.replacement.as_str()).into_owned()
+    }
+}
+
+/// `regex` and `captures` of a rule
+#[derive(Debug, Clone)]
+pub struct CompiledCaptures {
+    pub regex: Regex,
+    /// named group and mapping path, ordered by group
+    pub groups: Vec<(String, String)>,
+    pub on_no_match: OnNoMatch,
+}
+
+impl CompiledCaptures {
+    /// None for a rule without captures, every capture must be a named group of the regex
+    pub fn compile(rule: &ParserTransfromRule) -> Result<Option<Self>, TransformError> {
+        let regex_error = |message: String| TransformError::RegexError {
+            regex: rule.regex.clone(),
+            message,
+        };
+        match (rule.regex.is_empty(), rule.captures.is_empty()) {
+            (true, true) => return Ok(None),
+            (false, false) => {}
+            _ => return Err(regex_error("`regex` and `captures` go together".into())),
+        }
+        let regex = Regex::new(&prepare_rx_for_rust(&rule.regex)).map_err(|err| regex_error(err.to_string()))?;
+        let mut groups: Vec<(String, String)> = rule
+            .captures
+            .iter()
+            .map(|(group, path)| (group.clone(), path.clone()))
+            .collect();
+        groups.sort();
+        for (group, _) in groups.iter() {
+            if !regex.capture_names().flatten().any(|name| name == group) {
+                return Err(regex_error(format!("there is no named group [{group}]")));
+            }
+        }
+        Ok(Some(Self {
+            regex,
+            groups,
+            on_no_match: rule.on_no_match,
+        }))
+    }
+}
+
+/// `ParserTransfromRule` tree with selectors and regexes parsed once, see `compile_rules`
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub selector_str: String,
+    pub selector: CompiledSelector,
+    /// alternatives after `selector` with their text
+    pub fallbacks: Vec<(String, CompiledSelector)>,
+    pub default: Option<String>,
+    pub mapping: String,
+    pub attribute_name: String,
+    pub extract: ExtractTarget,
+    pub normalize: TextNormalizationOverride,
+    pub regex_sub: Option<CompiledRegexSub>,
+    pub filters: Vec<CompiledFilter>,
+    pub captures: Option<CompiledCaptures>,
+    pub parse_as: ParseAs,
+    pub children: Vec<CompiledRule>,
+    pub grouping: String,
+    pub exception_on_not_found: bool,
+    pub pick: Option<Pick>,
+    pub skip: usize,
+    pub limit: Option<usize>,
+    pub value_type: Option<ValueType>,
+    pub date_format: String,
+    pub on_type_error: OnTypeError,
+}
+
+impl CompiledRule {
+    pub fn compile(rule: &ParserTransfromRule) -> Result<Self, TransformError> {
+        Self::compile_in(rule, false)
+    }
+
+    /// `in_json` is set below a `parse_as: json` rule, only JSONPath selectors work there
+    fn compile_in(rule: &ParserTransfromRule, in_json: bool) -> Result<Self, TransformError> {
+        let mut compiled = Self::compile_node(rule, in_json)?;
+        compiled.children = rule
+            .children
+            .iter()
+            .map(|child| Self::compile_in(child, in_json || rule.parse_as == ParseAs::Json))
+            .collect::<Result<_, _>>()?;
+        Ok(compiled)
+    }
+
+    /// The rule without its children, `validate` compiles every rule of a tree this way
+    pub(crate) fn compile_node(rule: &ParserTransfromRule, in_json: bool) -> Result<Self, TransformError> {
+        // an empty `selector` with fallbacks takes the first of them
+        let mut alternatives = std::iter::once(&rule.selector)
+            .filter(|selector| !selector.is_empty())
+            .chain(rule.selectors.iter());
+        let selector_str = alternatives.next().cloned().unwrap_or_default();
+        let selector = CompiledSelector::parse(&selector_str, rule.selector_type)?;
+        let fallbacks = alternatives
+            .map(|selector| Ok((selector.clone(), CompiledSelector::parse(selector, rule.selector_type)?)))
+            .collect::<Result<Vec<_>, TransformError>>()?;
+        let context_error = |message: &str| TransformError::SelectorError {
+            selector: selector_str.clone(),
+            message: message.to_owned(),
+        };
+        let is_jsonpath = rule.selector_type == SelectorType::Jsonpath && !selector_str.is_empty();
+        if in_json && !is_jsonpath && !selector_str.is_empty() {
+            return Err(context_error("rules below `parse_as: json` need `selector_type: jsonpath`"));
+        }
+        if !in_json && is_jsonpath {
+            return Err(context_error("a jsonpath selector needs a `parse_as: json` parent rule"));
+        }
+        if in_json && rule.parse_as == ParseAs::Json {
+            return Err(context_error("`parse_as: json` can't be nested in JSON"));
+        }
+        if let Some(message) = rule.pick.as_ref().and_then(|pick| {
+            pick.conflict(!rule.mapping.is_empty(), !rule.children.is_empty(), !rule.captures.is_empty(), rule.extract.is_dict())
+        }) {
+            return Err(context_error(message));
+        }
+        if let Some(message) = rule.extract.conflict(&rule.attribute_name, !rule.captures.is_empty(), in_json) {
+            return Err(context_error(message));
+        }
+        let regex_sub = match rule.regex_sub_value.as_slice() {
+            [] => None,
+            [regex, replacement] => Some(CompiledRegexSub {
+                regex: Regex::new(&prepare_rx_for_rust(regex)).map_err(|err| TransformError::RegexError {
+                    regex: regex.clone(),
+                    message: err.to_string(),
+                })?,
+                replacement: prepare_rx_sub_for_replace(replacement),
+            }),
+            other => {
+                return Err(TransformError::RegexError {
+                    regex: format!("{other:?}"),
+                    message: "regex_sub_value needs a regex and a replacement".into(),
+                })
+            }
+        };
+        if rule.value_type == Some(ValueType::Date) && StrftimeItems::new(&rule.date_format).any(|item| item == Item::Error) {
+            return Err(TransformError::DateFormatError {
+                date_format: rule.date_format.clone(),
+            });
+        }
+        Ok(Self {
+            selector,
+            selector_str,
+            fallbacks,
+            default: rule.default.clone(),
+            mapping: rule.mapping.clone(),
+            attribute_name: rule.attribute_name.clone(),
+            extract: rule.extract,
+            normalize: rule.normalize.clone(),
+            regex_sub,
+            filters: compile_filters(&rule.filters)?,
+            captures: CompiledCaptures::compile(rule)?,
+            parse_as: rule.parse_as,
+            children: Vec::new(),
+            grouping: rule.grouping.clone(),
+            exception_on_not_found: rule.exception_on_not_found,
+            pick: rule.pick.clone(),
+            skip: rule.skip,
+            limit: rule.limit,
+            value_type: rule.value_type,
+            date_format: rule.date_format.clone(),
+            on_type_error: rule.on_type_error,
+        })
+    }
+
+    /// Matches of the first alternative which matches with its text, the text is None when
+    /// nothing matches, None for the empty selector
+    pub fn select_first<T>(
+        &self,
+        select: impl Fn(&CompiledSelector) -> Option<Vec<T>>,
+    ) -> Option<(Vec<T>, Option<&str>)> {
+        let found = select(&self.selector)?;
+        if !found.is_empty() {
+            return Some((found, Some(self.selector_str.as_str())));
+        }
+        for (selector_str, selector) in self.fallbacks.iter() {
+            match select(selector) {
+                Some(found) if !found.is_empty() => return Some((found, Some(selector_str.as_str()))),
+                _ => {}
+            }
+        }
+        Some((found, None))
+    }
+
+    /// Rules with fallbacks or a default record the matched alternative, see `MATCHED_SELECTORS_KEY`
+    pub fn push_matched_selector(&self, transformed_data: &mut TransformedData, matched: Option<&str>) {
+        if self.fallbacks.is_empty() && self.default.is_none() {
+            return;
+        }
+        let label = [&self.mapping, &self.grouping, &self.selector_str]
+            .into_iter()
+            .find(|label| !label.is_empty())
+            .map_or("", |label| label.as_str());
+        transformed_data.push_matched_selector(label, matched);
+    }
+
+    /// Writes `default` to the mapping when nothing matches, false for a rule without a default
+    pub fn push_default(&self, transformed_data: &mut TransformedData) -> Result<bool, TransformError> {
+        let Some(default) = &self.default else {
+            return Ok(false);
+        };
+        if !self.mapping.is_empty() {
+            transformed_data.push_value_path(&self.mapping, self.typed_value(default.clone())?);
+        }
+        Ok(true)
+    }
+
+    /// Matches after `skip` and `limit`, only the element of `first`, `last` and `nth` picks
+    pub fn picked<T>(&self, items: Vec<T>) -> Vec<T> {
+        let items: Vec<T> = items.into_iter().skip(self.skip).take(self.limit.unwrap_or(usize::MAX)).collect();
+        match &self.pick {
+            Some(pick) => pick.select(items),
+            None => items,
+        }
+    }
+
+    /// True when `pick` writes every match at once, see `push_picked_values`
+    pub fn picks_values(&self) -> bool {
+        match &self.pick {
+            Some(Pick::Join(_)) => true,
+            Some(pick) => pick.is_list() && self.children.is_empty(),
+            None => false,
+        }
+    }
+
+    /// True when the rule writes a mapping or captures
+    pub fn has_values(&self) -> bool {
+        !self.mapping.is_empty() || self.captures.is_some()
+    }
+
+    /// Every named group goes to its path, a group which doesn't take part is treated as a failed match
+    pub fn push_captures(&self, transformed_data: &mut TransformedData, text: &str) -> Result<(), TransformError> {
+        let Some(captures) = &self.captures else {
+            return Ok(());
+        };
+        let Some(found) = captures.regex.captures(text) else {
+            return match captures.on_no_match {
+                OnNoMatch::Skip => Ok(()),
+                OnNoMatch::Null => {
+                    for (_, path) in captures.groups.iter() {
+                        transformed_data.push_value_path(path, TransformedData::Null);
+                    }
+                    Ok(())
+                }
+                OnNoMatch::Error => Err(TransformError::NoMatchError {
+                    regex: captures.regex.to_string(),
+                    selector: self.selector_str.clone(),
+                }),
+            };
+        };
+        for (group, path) in captures.groups.iter() {
+            let value = match (found.name(group), captures.on_no_match) {
+                (Some(value), _) => self.typed_value(value.as_str().trim().to_string())?,
+                (None, OnNoMatch::Null) => TransformedData::Null,
+                (None, _) => continue,
+            };
+            transformed_data.push_value_path(path, value);
+        }
+        Ok(())
+    }
+
+    /// `regex_sub_value` and then every filter
+    pub fn filtered_text(&self, text: String) -> String {
+        let text = match &self.regex_sub {
+            Some(regex_sub) => regex_sub.replace_all(&text),
+            None => text,
+        };
+        self.filters.iter().fold(text, |text, filter| filter.apply(text))
+    }
+
+    /// Dict of a dict `extract`, every value is filtered and typed like a text
+    pub fn dict_value(&self, pairs: Vec<(String, String)>) -> Result<TransformedData, TransformError> {
+        let mut dict = TransformedData::create_data_map();
+        for (key, text) in pairs {
+            dict.insert(key, self.typed_value(self.filtered_text(text.trim().to_string()))?);
+        }
+        Ok(TransformedData::Dict(dict))
+    }
+
+    /// Text as is without a `type`, otherwise converted or handled by `on_type_error`
+    pub fn typed_value(&self, text: String) -> Result<TransformedData, TransformError> {
+        let Some(value_type) = self.value_type else {
+            return Ok(TransformedData::Value(text));
+        };
+        match (value_type.convert(&text, &self.date_format), self.on_type_error) {
+            (Some(value), _) => Ok(value),
+            (None, OnTypeError::Null) => Ok(TransformedData::Null),
+            (None, OnTypeError::Error) => Err(TransformError::TypeConversionError {
+                value: text,
+                value_type,
+                mapping: self.mapping.clone(),
+            }),
+        }
+    }
+}
+
+pub fn compile_rules(rules: &[ParserTransfromRule]) -> Result<Vec<CompiledRule>, TransformError> {
+    rules.iter().map(CompiledRule::compile).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_rules() {
+        let rule = |selector: &str, regex_sub_value: &[&str]| ParserTransfromRule {
+            selector: selector.into(),
+            mapping: "value".into(),
+            regex_sub_value: regex_sub_value.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        };
+        let compiled = CompiledRule::compile(&rule(
+            r#"li:-soup-contains("Bedrooms:") span"#,
+            &[r#".*?"geo":{"@type":"Geo",(\d{1,3}).*"#, r"\1"],
+        ))
+        .unwrap();
+        assert!(matches!(compiled.selector, CompiledSelector::Soup(_)));
+        let regex_sub = compiled.regex_sub.unwrap();
+        assert_eq!(regex_sub.replace_all(r#"x"geo":{"@type":"Geo",42}"#), "42");
+
+        assert!(matches!(CompiledRule::compile(&rule("", &[])).unwrap().selector, CompiledSelector::Empty));
+        assert!(matches!(
+            CompiledRule::compile(&rule("li[", &[])),
+            Err(TransformError::SelectorError { .. })
+        ));
+        assert!(matches!(
+            CompiledRule::compile(&rule("li", &["(unclosed", ""])),
+            Err(TransformError::RegexError { .. })
+        ));
+        assert!(matches!(
+            CompiledRule::compile(&rule("li", &["only regex"])),
+            Err(TransformError::RegexError { .. })
+        ));
+
+        let date_rule = ParserTransfromRule {
+            value_type: Some(ValueType::Date),
+            date_format: "%Y-%Q".into(),
+            ..rule("time", &[])
+        };
+        assert!(matches!(CompiledRule::compile(&date_rule), Err(TransformError::DateFormatError { .. })));
+
+        assert_eq!(prepare_rx_for_rust(r"a{2}b{1,}c{,3}d{,}"), r"a{2}b{1,}c{0,3}d\{,\}");
+        assert_eq!(prepare_rx_for_rust(r"\{x{y}"), r"\{x\{y\}");
+    }
+}
@@ -11,10 +11,19 @@ use std::rc::Rc;
 use std::str::{self, FromStr};
 use std::vec::Vec;
 
+use chrono::NaiveDate;
+
+use super::extract_target::ExtractTarget;
+use super::pick::Pick;
+use super::text_normalization::{TextNormalization, TextNormalizationOverride};
+use super::value_filter::ValueFilter;
+use super::value_type::{OnTypeError, ValueType};
+
 #[derive(Debug)]
 pub struct TransformSettings {
     pub max_depth_level: usize,
     pub default_key_name: String,
+    pub normalize: TextNormalization,
 }
 
 impl Default for TransformSettings {
@@ -22,6 +31,7 @@ impl Default for TransformSettings {
         TransformSettings {
             max_depth_level: 10_000,
             default_key_name: "list".into(),
+            normalize: TextNormalization::default(),
         }
     }
 }
@@ -32,21 +42,56 @@ pub static BS_CONTAINS_MARKER: &str = "/****/";
 lazy_static! {
     /// for fix regex diff from Python to Rust
     static ref RX_PAGE_NUM: Regex = Regex::new(r"\\(\d+)").expect("couldn't parse regex in [prepare_rx_sub_for_replace]");
-    /// for fix shortage rust BS vestion
-    static ref RX_BS_CONTAINS_PC: Regex = Regex::new(r#"(:-soup-contains\(\s?+"(.*?)"\s?+\))"#).expect("couldn't parse regex in [bs_py_adopt_contains]");
     
 }
 
 /// Convert Python replace substitution for Rust Regex
 pub fn prepare_rx_sub_for_replace(origin_rx: &str) -> String {
-    RX_PAGE_NUM.replace_all(origin_rx, "$$1").into_owned()
+    // `\2` is `${2}`, braces keep the group apart from following digits
+    RX_PAGE_NUM.replace_all(origin_rx, "$${${1}}").into_owned()
 }
 
-/// Exclude unsupport css selector part
-pub fn bs_py_adopt_contains(source_selector: &str) -> String {
-    RX_BS_CONTAINS_PC
-        .replace_all(&source_selector, format!("{BS_CONTAINS_MARKER}/*$1*/{BS_CONTAINS_MARKER}"))
-        .into_owned()
+/// Escape Python literal braces, Rust Regex takes `{` only as a quantifier
+pub fn prepare_rx_for_rust(origin_rx: &str) -> String {
+    let mut rx = String::with_capacity(origin_rx.len());
+    let mut rest = origin_rx;
+    while let Some(ch) = rest.chars().next() {
+        let len = match ch {
+            '\\' => rest.chars().nth(1).map_or(1, |next| 1 + next.len_utf8()),
+            '{' => {
+                let quantifier = rest[1..].find('}').filter(|&end| {
+                    let body = &rest[1..end + 1];
+                    !body.is_empty()
+                        && body != ","
+                        && body.splitn(2, ',').all(|n| n.chars().all(|c| c.is_ascii_digit()))
+                });
+                match quantifier {
+                    // Python `{,n}` is `{0,n}`
+                    Some(end) if rest[1..].starts_with(',') => {
+                        rx.push_str("{0");
+                        rx.push_str(&rest[1..end + 2]);
+                        rest = &rest[end + 2..];
+                        continue;
+                    }
+                    Some(end) => end + 2,
+                    None => {
+                        rx.push_str("\\{");
+                        rest = &rest[1..];
+                        continue;
+                    }
+                }
+            }
+            '}' => {
+                rx.push_str("\\}");
+                rest = &rest[1..];
+                continue;
+            }
+            _ => ch.len_utf8(),
+        };
+        rx.push_str(&rest[..len]);
+        rest = &rest[len..];
+    }
+    rx
 }
 
 #[derive(Debug, Clone, Error, Display)]
@@ -56,18 +101,101 @@ pub enum TransformError {
     RecursiveError { level: usize },
     #[display(fmt = "at least one tag for selector is not found [{}]", tag_name)]
     AtLeastOneTagNotFoundError { tag_name: String },
+    #[display(fmt = "couldn't parse selector [{}]: {}", selector, message)]
+    SelectorError { selector: String, message: String },
+    #[display(fmt = "couldn't compile regex [{}]: {}", regex, message)]
+    RegexError { regex: String, message: String },
+    #[display(fmt = "couldn't convert [{}] to [{:?}] for mapping [{}]", value, value_type, mapping)]
+    TypeConversionError { value: String, value_type: ValueType, mapping: String },
+    #[display(fmt = "regex [{}] doesn't match the text of [{}]", regex, selector)]
+    NoMatchError { regex: String, selector: String },
+    #[display(fmt = "couldn't parse JSON of [{}]: {}", selector, message)]
+    JsonError { selector: String, message: String },
+    #[display(fmt = "couldn't prepare filter [{}]: {}", filter, message)]
+    FilterError { filter: String, message: String },
+    #[display(fmt = "date_format [{}] is invalid", date_format)]
+    DateFormatError { date_format: String },
+}
+
+/// Language of `selector`
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SelectorType {
+    #[default]
+    Css,
+    /// relative paths start at the element of the parent rule
+    Xpath,
+    /// RFC 9535 JSONPath over the JSON of a `parse_as: json` ancestor
+    Jsonpath,
+}
+
+/// How children of a rule see the selected element
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseAs {
+    #[default]
+    Html,
+    /// text of the element after `regex_sub_value` and `filters` is parsed once as JSON,
+    /// children select from it with `selector_type: jsonpath`
+    Json,
+}
+
+/// What `captures` do when `regex` doesn't match the text, a group which doesn't take part
+/// in the match is written as null with `null` and left out otherwise
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnNoMatch {
+    /// no capture is written
+    #[default]
+    Skip,
+    /// every capture is written as null
+    Null,
+    Error,
 }
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize, From, PartialEq)]
 #[serde(default)]
 pub struct ParserTransfromRule {
+    /// name of the rule for overrides of `extends` sources, `selector` is used without it
+    pub id: String,
     pub selector: String,
+    /// fallbacks of `selector` with the same `selector_type`, the first alternative which matches wins
+    pub selectors: Vec<String>,
+    /// value of the mapping when no alternative matches, `exception_on_not_found` isn't raised then
+    pub default: Option<String>,
+    pub selector_type: SelectorType,
+    pub parse_as: ParseAs,
     pub mapping: String,
     pub attribute_name: String,
+    /// what is taken from the selected element, see `ExtractTarget`
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub extract: ExtractTarget,
+    /// overrides `normalize` of `TransformSettings` for the rule
+    pub normalize: TextNormalizationOverride,
+    /// shorthand for a `regex_replace` step running before `filters`
     pub regex_sub_value: Vec<String>,
+    /// steps applied in order to the extracted text, see `ValueFilter`
+    #[serde(with = "serde_yaml::with::singleton_map_recursive")]
+    pub filters: Vec<ValueFilter>,
+    /// regex with named groups for `captures`, it runs once on the filtered text
+    pub regex: String,
+    /// named group of `regex` to its mapping path
+    pub captures: HashMap<String, String>,
+    pub on_no_match: OnNoMatch,
     pub children: Rc<Vec<Self>>,
     pub grouping: String,
     pub exception_on_not_found: bool,
+    /// shape of the output whatever the number of matches, see `Pick`
+    pub pick: Option<Pick>,
+    /// matched elements dropped from the start before `limit` and `pick`
+    pub skip: usize,
+    pub limit: Option<usize>,
+    /// converts the value from text, see `ValueType`
+    #[serde(rename = "type")]
+    pub value_type: Option<ValueType>,
+    /// chrono format of the `date` type, `%Y-%m-%d` when empty
+    pub date_format: String,
+    pub on_type_error: OnTypeError,
 }
 
 #[allow(dead_code)]
@@ -96,26 +224,6 @@ impl ParserTransfromRule {
         }
     }
 
-    /// check is contains and return search text
-    #[inline]
-    pub fn is_contains_selector(&self) -> Option<(String, (String, String))> {
-        let text = RX_BS_CONTAINS_PC.captures(&self.selector)
-                    .map(|s| {
-                        s.get(2)
-                        .map(|s| s.as_str()).unwrap_or("")
-                    })
-                    .map(String::from);
-        if text == None {
-            return None;
-        }
-        let mut sp = RX_BS_CONTAINS_PC.splitn(&self.selector, 2)
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty());
-        Some(
-            ( text.unwrap(), (sp.next().unwrap(), sp.next().unwrap_or("".into())) )
-        )
-    }
-
 }
 
 #[derive(From, Debug)]
@@ -140,9 +248,25 @@ pub enum TransformedData {
     Dict(DataMap),
     List(DataVec),
     Value(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    #[serde(serialize_with = "serialize_decimal")]
+    Decimal(String),
+    Date(NaiveDate),
+    Null,
+}
+
+/// Decimal text is a JSON number already, it's written as is to keep the precision
+fn serialize_decimal<S: serde::Serializer>(value: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serde_json::value::RawValue::from_string(value.to_owned())
+        .map_err(serde::ser::Error::custom)?
+        .serialize(serializer)
 }
 
 pub const UNSUPPORTED_ENUM_TYPE: &str = "with unsupported TransformData enum type";
+/// Dict of a rule with fallback selectors to the alternative which matched, null when none matched
+pub const MATCHED_SELECTORS_KEY: &str = "_matched_selectors";
 
 impl From<String> for TransformedData {
     fn from(value: String) -> Self {
@@ -178,7 +302,7 @@ impl Into<String> for TransformedData {
     fn into(self) -> String {
         match self {
             TransformedData::Value(s) => s,
-            _ => self.to_json_string(),
+            _ => self.scalar_text().unwrap_or_else(|| self.to_json_string()),
         }
     }
 }
@@ -212,6 +336,20 @@ impl TransformedData {
             TransformedData::Dict(dict) => dict.is_empty(),
             TransformedData::List(list) => list.is_empty(),
             TransformedData::Value(string) => string.is_empty(),
+            TransformedData::Null => true,
+            _ => false,
+        }
+    }
+
+    /// Plain text of a value, None for null and containers
+    pub fn scalar_text(&self) -> Option<String> {
+        match self {
+            TransformedData::Value(value) | TransformedData::Decimal(value) => Some(value.clone()),
+            TransformedData::Int(value) => Some(value.to_string()),
+            TransformedData::Float(value) => Some(value.to_string()),
+            TransformedData::Bool(value) => Some(value.to_string()),
+            TransformedData::Date(value) => Some(value.to_string()),
+            TransformedData::Dict(_) | TransformedData::List(_) | TransformedData::Null => None,
         }
     }
 
@@ -356,6 +494,20 @@ impl TransformedData {
         return None;
     }
 
+    /// Records the matched alternative of the rule, only a dict keeps it
+    pub fn push_matched_selector(&mut self, label: &str, matched: Option<&str>) {
+        let TransformedData::Dict(dict) = self else {
+            return;
+        };
+        let matched_selectors = dict
+            .entry(MATCHED_SELECTORS_KEY.to_owned())
+            .or_insert_with(TransformedData::create_dict);
+        if let TransformedData::Dict(matched_selectors) = matched_selectors {
+            let matched = matched.map_or(TransformedData::Null, |selector| TransformedData::Value(selector.to_owned()));
+            matched_selectors.insert(label.to_owned(), matched);
+        }
+    }
+
     pub fn to_json_string(&self) -> String {
         serde_json::to_string(self).ok().unwrap()
     }
@@ -368,6 +520,8 @@ impl fmt::Display for TransformedData {
             TransformedData::Dict(d) => format!("Dict({})", d.len()),
             TransformedData::List(l) => format!("List({})", l.len()),
             TransformedData::Value(v) => format!("Value({})", v.len()),
+            TransformedData::Null => "Null".to_string(),
+            other => format!("Scalar({})", other.scalar_text().unwrap_or_default()),
         })
     }
 }
@@ -448,27 +602,15 @@ mod tests {
     }
 
     #[test]
-    fn contains_selector_test() {
-        prepare_test_logs();
-        let rule = ParserTransfromRule{ 
-            selector: r#"li.property-facts__item"#.to_string(),
-             ..Default::default() 
-        };
-        
-        let _ = {
-            let res = rule.is_contains_selector();
-            assert_eq!(res, None);
-        };
-        
-        let rule = ParserTransfromRule{ 
-            selector: r#"li.property-facts__item:-soup-contains( "Property type:")/*("")*/ .property-facts__value"#.to_string(),
-             ..Default::default() 
-        };
-        let Some((txt, (left, right))) = rule.is_contains_selector() else { panic!("empty result") };
-        
-        assert_eq!(txt, "Property type:");
-        assert_eq!(left, "li.property-facts__item");
-        assert_eq!(right, r#"/*("")*/ .property-facts__value"#); 
+    fn regex_sub_group_references() -> Result<(), anyhow::Error> {
+        let rstr = prepare_rx_sub_for_replace(r"\2-\1");
+        assert_eq!(Regex::new(r"(a)(b)")?.replace_all("ab", rstr), "b-a");
+
+        // `cards_limit` of bayut
+        let rstr = prepare_rx_sub_for_replace(r"\1\2\3\4");
+        let rx = Regex::new(r".*? of (\d+)?,?(\d+)?,?(\d+)?,?(\d+)?,? .*")?;
+        assert_eq!(rx.replace_all("1 - 24 of 1,005 properties", rstr), "1005");
+        Ok(())
     }
 
     #[test]
@@ -479,6 +621,7 @@ mod tests {
         let rx = Regex::new(r"(\d+)")?;
         let res = rx.replace_all("123445", rstr).into_owned();
         info!("res = [{res}]");
+        assert_eq!(res, "page-123445");
 
         Ok(())
     }
@@ -0,0 +1,205 @@
+use scraper::{ElementRef, Node};
+use serde::{Deserialize, Serialize};
+
+/// `extract` of a rule, what is taken from the selected element
+///
+/// ```yaml
+/// extract: inner_html
+/// extract: { text_node: -1 }
+/// extract: srcset
+/// attribute_name: data-srcset
+/// ```
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractTarget {
+    /// text of all descendants joined with spaces, or `attribute_name`
+    #[default]
+    Text,
+    InnerHtml,
+    OuterHtml,
+    /// text nodes which are children of the element only
+    OwnText,
+    /// descendant text node by index without whitespace only nodes, a negative index counts from the end
+    TextNode(i64),
+    /// descriptor like `480w` or `2x` to the url, of `attribute_name` or `srcset`
+    Srcset,
+    /// `data-*` attributes without the prefix
+    Data,
+    /// every attribute
+    Attributes,
+}
+
+/// Extracted text, or pairs for a dict of the mapping
+#[derive(Debug, Clone, PartialEq)]
+pub enum Extracted {
+    Text(String),
+    Dict(Vec<(String, String)>),
+}
+
+/// Candidates of a `srcset` value, a candidate without a descriptor is `1x`. A url runs up to
+/// whitespace and may have commas inside like `/w_480,h_320/a.jpg`, candidates are split by the
+/// comma after the descriptor or at the end of the url as in the HTML srcset grammar
+fn parse_srcset(srcset: &str) -> Vec<(String, String)> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|ch: char| ch.is_whitespace() || ch == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let (url, after) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        let trimmed = url.trim_end_matches(',');
+        if trimmed.len() < url.len() {
+            candidates.push(("1x".to_owned(), trimmed.to_owned()));
+            rest = after;
+            continue;
+        }
+        let (descriptors, after) = after.split_at(after.find(',').unwrap_or(after.len()));
+        let descriptor = descriptors.split_whitespace().next().unwrap_or("1x");
+        candidates.push((descriptor.to_owned(), url.to_owned()));
+        rest = after;
+    }
+}
+
+impl ExtractTarget {
+    /// True for targets which write a dict
+    pub fn is_dict(&self) -> bool {
+        matches!(self, ExtractTarget::Srcset | ExtractTarget::Data | ExtractTarget::Attributes)
+    }
+
+    /// Problem of the target with other fields of the rule, `in_json` is set below a `parse_as: json` rule
+    pub fn conflict(&self, attribute_name: &str, has_captures: bool, in_json: bool) -> Option<&'static str> {
+        if in_json && *self != ExtractTarget::Text {
+            Some("`extract` works on HTML elements only")
+        } else if !attribute_name.is_empty() && !matches!(self, ExtractTarget::Text | ExtractTarget::Srcset) {
+            Some("`attribute_name` goes with `extract: text` or `extract: srcset` only")
+        } else if has_captures && self.is_dict() {
+            Some("`captures` need a text `extract`")
+        } else {
+            None
+        }
+    }
+
+    /// True for targets which keep markup, text normalization skips them
+    pub fn is_markup(&self) -> bool {
+        matches!(self, ExtractTarget::InnerHtml | ExtractTarget::OuterHtml)
+    }
+
+    /// `join_separator` goes between text nodes
+    pub fn extract(&self, element: &ElementRef, attribute_name: &str, join_separator: &str) -> Extracted {
+        let text = match self {
+            ExtractTarget::Text if attribute_name.is_empty() || attribute_name == "text" => {
+                element.text().collect::<Vec<_>>().join(join_separator)
+            }
+            ExtractTarget::Text => element.attr(attribute_name).unwrap_or("").to_owned(),
+            ExtractTarget::InnerHtml => element.inner_html(),
+            ExtractTarget::OuterHtml => element.html(),
+            ExtractTarget::OwnText => element
+                .children()
+                .filter_map(|child| match child.value() {
+                    Node::Text(text) => Some(&text[..]),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(join_separator),
+            ExtractTarget::TextNode(index) => {
+                let nodes: Vec<&str> = element.text().filter(|text| !text.trim().is_empty()).collect();
+                let index = if *index < 0 {
+                    usize::try_from(index.unsigned_abs())
+                        .ok()
+                        .and_then(|from_end| nodes.len().checked_sub(from_end))
+                } else {
+                    usize::try_from(*index).ok()
+                };
+                index
+                    .and_then(|index| nodes.get(index))
+                    .map(|text| text.to_string())
+                    .unwrap_or_default()
+            }
+            ExtractTarget::Srcset => {
+                let attribute_name = if attribute_name.is_empty() { "srcset" } else { attribute_name };
+                return Extracted::Dict(parse_srcset(element.attr(attribute_name).unwrap_or("")));
+            }
+            ExtractTarget::Data => {
+                return Extracted::Dict(
+                    element
+                        .value()
+                        .attrs()
+                        .filter_map(|(name, value)| Some((name.strip_prefix("data-")?.to_owned(), value.to_owned())))
+                        .collect(),
+                )
+            }
+            ExtractTarget::Attributes => {
+                return Extracted::Dict(
+                    element
+                        .value()
+                        .attrs()
+                        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                        .collect(),
+                )
+            }
+        };
+        Extracted::Text(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::{Html, Selector};
+
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let html = Html::parse_fragment(
+            r#"<div class="d" data-id="7" data-kind="villa">Intro <b>bold</b> <i> </i>tail<br>last</div>
+            <img src="a.jpg" srcset="a-480.jpg 480w, a-800.jpg 800w, a.jpg">"#,
+        );
+        let div = html.select(&Selector::parse("div").unwrap()).next().unwrap();
+        let img = html.select(&Selector::parse("img").unwrap()).next().unwrap();
+        let text = |target: ExtractTarget, element: &ElementRef| match target.extract(element, "", " ") {
+            Extracted::Text(text) => text,
+            Extracted::Dict(_) => panic!("{target:?} isn't text"),
+        };
+        let dict = |target: ExtractTarget, element: &ElementRef, attribute_name: &str| {
+            match target.extract(element, attribute_name, " ") {
+                Extracted::Dict(mut pairs) => {
+                    pairs.sort();
+                    pairs
+                }
+                Extracted::Text(_) => panic!("{target:?} isn't a dict"),
+            }
+        };
+        let pairs = |values: &[(&str, &str)]| -> Vec<(String, String)> {
+            values.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(text(ExtractTarget::Text, &div), "Intro  bold     tail last");
+        assert_eq!(ExtractTarget::Text.extract(&div, "data-id", " "), Extracted::Text("7".into()));
+        assert_eq!(ExtractTarget::Text.extract(&div, "", "|"), Extracted::Text("Intro |bold| | |tail|last".into()));
+        assert_eq!(text(ExtractTarget::InnerHtml, &div), "Intro <b>bold</b> <i> </i>tail<br>last");
+        assert!(text(ExtractTarget::OuterHtml, &div).starts_with("<div "));
+        assert_eq!(text(ExtractTarget::OwnText, &div), "Intro    tail last");
+        assert_eq!(text(ExtractTarget::TextNode(1), &div), "bold");
+        assert_eq!(text(ExtractTarget::TextNode(-1), &div), "last");
+        assert_eq!(text(ExtractTarget::TextNode(9), &div), "");
+        assert_eq!(
+            dict(ExtractTarget::Srcset, &img, ""),
+            pairs(&[("1x", "a.jpg"), ("480w", "a-480.jpg"), ("800w", "a-800.jpg")])
+        );
+        assert_eq!(dict(ExtractTarget::Srcset, &img, "data-srcset"), pairs(&[]));
+        assert_eq!(
+            parse_srcset("https://cdn.io/w_480,h_320/a.jpg 480w,https://cdn.io/w_800,h_600/a.jpg 800w, b.jpg, c.jpg,d.jpg 2x"),
+            pairs(&[
+                ("480w", "https://cdn.io/w_480,h_320/a.jpg"),
+                ("800w", "https://cdn.io/w_800,h_600/a.jpg"),
+                ("1x", "b.jpg"),
+                ("2x", "c.jpg,d.jpg")
+            ])
+        );
+        assert_eq!(dict(ExtractTarget::Data, &div, ""), pairs(&[("id", "7"), ("kind", "villa")]));
+        assert_eq!(
+            dict(ExtractTarget::Attributes, &div, ""),
+            pairs(&[("class", "d"), ("data-id", "7"), ("data-kind", "villa")])
+        );
+    }
+}
@@ -1,82 +1,212 @@
-use regex::Regex;
-use scraper::{self, selectable::Selectable, ElementRef};
-use std::cmp::min;
+use scraper::{self, ElementRef};
 use std::rc::Weak;
 use std::str;
 use std::vec::Vec;
 
 use tracing::{debug, info, warn};
 
+pub mod compiled_rule;
 pub mod defs;
+pub mod extract_target;
+pub mod pick;
+pub mod soup_selector;
+pub mod text_normalization;
+pub mod value_filter;
+pub mod value_type;
+pub mod xpath_selector;
+use compiled_rule::*;
 use defs::*;
+use extract_target::Extracted;
+use pick::Pick;
 
-fn py_adopt_rx((rx, plcmnt): (&str, &str)) -> (String, String) {
-    (rx.to_owned(), defs::prepare_rx_sub_for_replace(plcmnt))
+/// Where values of the rule go: a new dict of the `grouping` list, a dict for the mapping
+/// or the data itself
+fn rule_output<'d>(transformed_data: &'d mut TransformedData, rule: &CompiledRule, nested: bool) -> &'d mut TransformedData {
+    let (group, obj) = (!nested && !rule.grouping.is_empty(), rule.has_values());
+
+    if group {
+        transformed_data.as_group_list_wrapper(&rule.grouping)
+    } else if obj {
+        transformed_data.as_map_wrapper()
+    } else {
+        transformed_data
+    }
+}
+
+/// Text of the element parsed as JSON, None when it isn't JSON and the rule doesn't raise
+fn parse_json(rule: &CompiledRule, soup: &scraper::ElementRef) -> Result<Option<serde_json::Value>, TransformError> {
+    let text = rule.filtered_text(soup.text().collect::<String>().trim().to_string());
+    match serde_json::from_str(&text) {
+        Ok(json) => Ok(Some(json)),
+        Err(err) if rule.exception_on_not_found => Err(TransformError::JsonError {
+            selector: rule.selector_str.clone(),
+            message: err.to_string(),
+        }),
+        Err(err) => {
+            warn!("couldn't parse JSON of [{}]: {err}", rule.selector_str);
+            Ok(None)
+        }
+    }
+}
+
+/// Normalized text of the JSON node
+fn json_extract(rule: &CompiledRule, node: &serde_json::Value, settings: &TransformSettings) -> Extracted {
+    let text = match node {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Null => String::new(),
+        other => other.to_string(),
+    };
+    Extracted::Text(settings.normalize.with_override(&rule.normalize).apply(text))
+}
+
+/// Target of the rule taken from the element, normalized unless it's markup
+fn html_extract(rule: &CompiledRule, element: &scraper::ElementRef, settings: &TransformSettings) -> Extracted {
+    let normalize = settings.normalize.with_override(&rule.normalize);
+    let normalize_text = |text: String| if rule.extract.is_markup() { text } else { normalize.apply(text) };
+    match rule.extract.extract(element, &rule.attribute_name, &normalize.join_separator) {
+        Extracted::Text(text) => Extracted::Text(normalize_text(text)),
+        Extracted::Dict(pairs) => {
+            Extracted::Dict(pairs.into_iter().map(|(key, text)| (key, normalize_text(text))).collect())
+        }
+    }
 }
 
-fn select_contains<'a, 'b>(
+/// Captures and the mapping of the filtered text
+fn push_text(transformed_data: &mut TransformedData, rule: &CompiledRule, handled_text: String) -> Result<(), TransformError> {
+    debug!("push value {}", handled_text.chars().take(10).collect::<String>());
+    rule.push_captures(transformed_data, &handled_text)?;
+    if !rule.mapping.is_empty() {
+        transformed_data.push_value_path(&rule.mapping, rule.typed_value(handled_text)?);
+    }
+    Ok(())
+}
+
+fn push_extracted(transformed_data: &mut TransformedData, rule: &CompiledRule, extracted: Extracted) -> Result<(), TransformError> {
+    match extracted {
+        Extracted::Text(text) => push_text(transformed_data, rule, rule.filtered_text(text.trim().to_string())),
+        Extracted::Dict(pairs) => {
+            if !rule.mapping.is_empty() {
+                transformed_data.push_value_path(&rule.mapping, rule.dict_value(pairs)?);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// `pick: all` and `unique` write a list of values of every match, `join` writes one text
+fn push_picked_values(
     transformed_data: &mut TransformedData,
-    soup: &'b scraper::ElementRef,
-    rule: &'a ParserTransfromRule,
+    rule: &CompiledRule,
+    extracted: Vec<Extracted>,
+) -> Result<(), TransformError> {
+    if let Some(Pick::Join(separator)) = &rule.pick {
+        let texts: Vec<String> = extracted
+            .into_iter()
+            .filter_map(|extracted| match extracted {
+                Extracted::Text(text) => Some(rule.filtered_text(text.trim().to_string())),
+                Extracted::Dict(_) => None,
+            })
+            .filter(|text| !text.is_empty())
+            .collect();
+        return push_text(transformed_data, rule, texts.join(separator));
+    }
+    let mut values = Vec::new();
+    for extracted in extracted {
+        let value = match extracted {
+            Extracted::Text(text) => rule.typed_value(rule.filtered_text(text.trim().to_string()))?,
+            Extracted::Dict(pairs) => rule.dict_value(pairs)?,
+        };
+        if rule.pick != Some(Pick::Unique) || !values.contains(&value) {
+            values.push(value);
+        }
+    }
+    if !rule.mapping.is_empty() {
+        transformed_data.push_value_path(&rule.mapping, TransformedData::List(Box::new(values)));
+    }
+    Ok(())
+}
+
+/// Same as `transform_html_single` over a JSON node with JSONPath selectors
+fn transform_json_single(
+    transformed_data: &mut TransformedData,
+    json: &serde_json::Value,
+    rule: &CompiledRule,
+    nested: bool,
     level: usize,
     settings: &TransformSettings,
-) -> Result<bool, TransformError> {
-    let Some((text, (left, right))) = rule.is_contains_selector() else {
-        return Ok(false);
-    };
+) -> Result<(), TransformError> {
+    let debmr = "| ".repeat(level);
+    debug!("{debmr} json rule [{}, {}]", if nested { "" } else { rule.selector_str.as_str() }, &rule.mapping);
+    if level >= settings.max_depth_level {
+        return Err(TransformError::RecursiveError { level });
+    }
 
-    let selector = scraper::Selector::parse(&left).unwrap();
-    let tags: Vec<scraper::ElementRef<'_>> = if left.is_empty() {
-        soup.select(&selector).collect()
-    } else {
-        vec![soup.clone()]
-    };
+    let transformed_data_out = rule_output(transformed_data, rule, nested);
 
-    for ele in tags {
-        if ele.text().find(|s| *s == text).is_none() {
-            continue;
+    let selected_nodes = if nested { None } else { rule.select_first(|selector| selector.select_json(json)) };
+    let selected_json = if let Some((nodes, matched)) = selected_nodes {
+        rule.push_matched_selector(transformed_data_out, matched);
+        let nodes = rule.picked(nodes);
+        if nodes.is_empty() && rule.push_default(transformed_data_out)? {
+            return Ok(());
         }
-        if right.is_empty() {
-            transform_html_single(
-                transformed_data,
-                &ele,
-                &rule.with_empty_selector(),
-                level,
-                settings,
-            )?;
-            continue;
+        if nodes.is_empty() && rule.exception_on_not_found {
+            return Err(TransformError::AtLeastOneTagNotFoundError {
+                tag_name: rule.selector_str.clone(),
+            });
+        }
+        if rule.picks_values() {
+            let extracted = nodes.iter().map(|node| json_extract(rule, node, settings)).collect();
+            return push_picked_values(transformed_data_out, rule, extracted);
+        }
+        if nodes.is_empty() {
+            return Ok(());
         }
+        if nodes.len() > 1 || rule.pick == Some(Pick::All) {
+            for node in nodes {
+                transform_json_single(transformed_data_out, node, rule, true, level + 1, settings)?
+            }
+            return Ok(());
+        }
+        nodes[0]
+    } else {
+        json
+    };
 
-        let selector = scraper::Selector::parse(&right).unwrap();
-        let tags: Vec<scraper::ElementRef<'_>> = ele.select(&selector).collect();
-
-        for ele in tags {
-            transform_html_single(
-                transformed_data,
-                &ele,
-                &rule.with_empty_selector(),
-                level,
-                settings,
-            )?;
+    if rule.has_values() {
+        push_extracted(transformed_data_out, rule, json_extract(rule, selected_json, settings))?;
+    }
+
+    if !rule.children.is_empty() {
+        let children_out = if rule.children.len() > 1 {
+            transformed_data_out.as_map_wrapper()
+        } else {
+            transformed_data_out
+        };
+        for child in rule.children.iter() {
+            transform_json_single(children_out, selected_json, child, false, level + 1, settings)?
         }
     }
 
-    Ok(true)
+    Ok(())
 }
 
+/// `nested` is set for every tag of a multi tag match,
+/// selector and grouping of the rule are handled by the caller then
 fn transform_html_single<'a, 'b>(
     transformed_data: &mut TransformedData,
     soup: &'b scraper::ElementRef,
-    rule: &'a ParserTransfromRule,
+    rule: &'a CompiledRule,
+    nested: bool,
     level: usize,
     settings: &TransformSettings,
 ) -> Result<(), TransformError> {
     let debmr = "| ".repeat(level);
     debug!(
         "{debmr} rule [{}, {}, {}, {}, {:?}]",
-        rule.selector,
+        if nested { "" } else { rule.selector_str.as_str() },
         &rule.mapping,
-        &rule.grouping,
+        if nested { "" } else { rule.grouping.as_str() },
         rule.children.len(),
         soup.id(),
     );
@@ -84,59 +214,40 @@ fn transform_html_single<'a, 'b>(
         return Err(TransformError::RecursiveError { level });
     }
 
-    let handle_regex = |rx: &Vec<String>, txt: &str| {
-        let (left, right) = py_adopt_rx((&rx[0], &rx[1]));
-        let regex = Regex::new(&left).unwrap();
-        return regex.replace_all(txt, right).into_owned();
-    };
-    let handle_attr = |selected_soup: &scraper::ElementRef, attr_name: &str| {
-        let attr = selected_soup.attr(attr_name);
-        return String::from(attr.unwrap_or(""));
-    };
-
-    let transformed_data_out: &mut TransformedData = {
-        let (group, obj) = (!rule.grouping.is_empty(), !rule.mapping.is_empty());
-
-        if group {
-            transformed_data.as_group_list_wrapper(&rule.grouping)
-        } else if obj {
-            transformed_data.as_map_wrapper()
-        } else {
-            transformed_data
-        }
-    };
-
-    // grouping has handled
-    let rule = rule.with_empty_grouping();
+    let transformed_data_out = rule_output(transformed_data, rule, nested);
 
     debug!("{debmr} transformed_data_out is {transformed_data_out}");
 
-    if select_contains(transformed_data_out, &soup, &rule, level, settings)? {
-        return Ok(());
-    }
-
-    let selected_soup = if !rule.selector.is_empty() {
-        let selector_str: &str = rule.selector.as_str();
-        let sc_selector = scraper::Selector::parse(selector_str).unwrap();
-        let tags: Vec<scraper::ElementRef<'_>> = soup.select(&sc_selector).collect();
+    let selected_tags = if nested { None } else { rule.select_first(|selector| selector.select(soup)) };
+    let selected_soup = if let Some((tags, matched)) = selected_tags {
+        let selector_str: &str = matched.unwrap_or(rule.selector_str.as_str());
         debug!("{debmr} selector_str [{selector_str}] tags count [{}]", tags.len());
-        
+        rule.push_matched_selector(transformed_data_out, matched);
+        let tags = rule.picked(tags);
+
+        if tags.is_empty() && rule.push_default(transformed_data_out)? {
+            return Ok(());
+        }
         if tags.len() == 0 && rule.exception_on_not_found {
             return Err(TransformError::AtLeastOneTagNotFoundError {
-                tag_name: rule.selector.clone(),
+                tag_name: rule.selector_str.clone(),
             });
         }
+        if rule.picks_values() {
+            let extracted = tags.iter().map(|tag| html_extract(rule, tag, settings)).collect();
+            return push_picked_values(transformed_data_out, rule, extracted);
+        }
         if tags.len() == 0 {
             return Ok(());
         }
-        if tags.len() > 1 {
-            let nested_rule = rule.with_empty_selector();
+        if tags.len() > 1 || rule.pick == Some(Pick::All) {
             for (idx, &ele) in tags.iter().enumerate() {
                 debug!("{debmr} push tag items, tag index [{idx} {:?}]", ele.id());
                 transform_html_single(
                     transformed_data_out,
                     &ele,
-                    &nested_rule,
+                    rule,
+                    true,
                     level + 1,
                     settings,
                 )?
@@ -151,45 +262,29 @@ fn transform_html_single<'a, 'b>(
 
     let _ = soup;
 
-    if !rule.mapping.is_empty() {
-        let attr_name = if !rule.attribute_name.is_empty() {
-            rule.attribute_name.as_str()
-        } else {
-            "text"
-        };
-        let text = if attr_name == "text" {
-            selected_soup.text().collect::<Vec<_>>().join(" ")
-        } else {
-            handle_attr(&selected_soup, attr_name)
-        };
-
-        let handled_text = if !rule.regex_sub_value.is_empty() {
-            handle_regex(&rule.regex_sub_value, &text.trim())
-        } else {
-            text.trim().to_string()
-        };
-
-        debug!(
-            "{debmr} push value {}",
-            &handled_text[0..min(handled_text.len(), 10)]
-        );
-        transformed_data_out
-            .push_value_path(&rule.mapping, TransformedData::Value(handled_text.into()));
+    if rule.has_values() {
+        push_extracted(transformed_data_out, rule, html_extract(rule, &selected_soup, settings))?;
     }
 
     if !rule.children.is_empty() {
         debug!("{debmr} handling of children");
-        transform_html_multi(
-            if rule.children.len() > 1 {
-                transformed_data_out.as_map_wrapper()
-            } else {
-                transformed_data_out
-            },
-            &selected_soup,
-            rule.children.as_slice(),
-            level + 1,
-            settings,
-        )?;
+        let children_out = if rule.children.len() > 1 {
+            transformed_data_out.as_map_wrapper()
+        } else {
+            transformed_data_out
+        };
+        match rule.parse_as {
+            ParseAs::Html => {
+                transform_html_multi(children_out, &selected_soup, rule.children.as_slice(), level + 1, settings)?
+            }
+            ParseAs::Json => {
+                if let Some(json) = parse_json(rule, &selected_soup)? {
+                    for child in rule.children.iter() {
+                        transform_json_single(children_out, &json, child, false, level + 1, settings)?
+                    }
+                }
+            }
+        }
     }
 
     Ok(())
@@ -198,12 +293,12 @@ fn transform_html_single<'a, 'b>(
 fn transform_html_multi<'a, 'b>(
     transoftmed_data: &mut TransformedData,
     soup: &'b scraper::ElementRef,
-    rules: &[ParserTransfromRule],
+    rules: &[CompiledRule],
     level: usize,
     settings: &TransformSettings,
 ) -> Result<(), TransformError> {
     for ele in rules {
-        transform_html_single(transoftmed_data, soup, ele, level, settings)?
+        transform_html_single(transoftmed_data, soup, ele, false, level, settings)?
     }
     Ok(())
 }
@@ -211,7 +306,7 @@ fn transform_html_multi<'a, 'b>(
 fn transform_html_inner<'a, 'b, 'c>(
     transformed_data: &'c mut TransformedData,
     html: &'b str,
-    rules: &[ParserTransfromRule],
+    rules: &[CompiledRule],
     settings: &TransformSettings,
 ) -> Result<(), TransformError> {
     let parsed = scraper::Html::parse_document(html);
@@ -222,7 +317,7 @@ fn transform_html_inner<'a, 'b, 'c>(
 #[inline]
 pub fn transform_html_map<'a, 'b, 'c>(
     html: &'b str,
-    rules: &[ParserTransfromRule],
+    rules: &[CompiledRule],
     settings: &TransformSettings,
 ) -> Result<DataMap, TransformError> {
     let mut data = TransformedData::Dict(TransformedData::create_data_map());
@@ -236,7 +331,7 @@ pub fn transform_html_map<'a, 'b, 'c>(
 #[inline]
 pub fn transform_html<'a, 'b, 'c>(
     html: &'b str,
-    rules: &[ParserTransfromRule],
+    rules: &[CompiledRule],
     settings: &TransformSettings,
 ) -> Result<TransformedData, TransformError> {
     let mut data = TransformedData::create_dict();
@@ -247,7 +342,7 @@ pub fn transform_html<'a, 'b, 'c>(
 pub fn transform_html_list<'a, 'b, 'c>(
     transformed_data: DataVec,
     html: &'b str,
-    rules: &[ParserTransfromRule],
+    rules: &[CompiledRule],
     settings: &TransformSettings,
 ) -> Result<(), TransformError> {
     let mut data = TransformedData::List(transformed_data);
@@ -262,7 +357,11 @@ mod tests {
     use tracing::info;
     use tracing_subscriber::fmt::format::FmtSpan;
 
+    use regex::Regex;
+
     use super::*;
+    use value_filter::ValueFilter;
+    use value_type::{OnTypeError, ValueType};
 
     fn prepare_test_logs() {
         let _ = tracing_subscriber::fmt()
@@ -328,7 +427,7 @@ mod tests {
             },
             rl::from_str(r#"{ "selector": ".test1", "mapping": "test_json" }"#).unwrap(),
         ];
-        let data = transform_html_map(html, &rules, &TransformSettings::default()).expect("Err");
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err");
         info!("{data:#?}");
         assert_eq!(data["place"], TransformedData::from("Foo"));
         assert_eq!(data["place"], data["place2"]);
@@ -375,7 +474,8 @@ mod tests {
         match transform_html_single(
             &mut transformed_data,
             &doc.root_element(),
-            &rules[0],
+            &CompiledRule::compile(&rules[0]).unwrap(),
+            false,
             1,
             &TransformSettings {
                 max_depth_level: 2,
@@ -392,7 +492,8 @@ mod tests {
         match transform_html_single(
             &mut transformed_data,
             &doc.root_element(),
-            &rules[1],
+            &CompiledRule::compile(&rules[1]).unwrap(),
+            false,
             0,
             &TransformSettings::default(),
         ) {
@@ -498,8 +599,484 @@ path/to/baz:3:It's a Trap!
                 ..Default::default()
             },
         ];
-        let data = transform_html_map(html, &rules, &TransformSettings::default()).expect("Err");
-        info!("data = [{data:#?}]")
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err");
+        info!("data = [{data:#?}]");
+        assert_eq!(data["place"], "some text".into());
+        assert_eq!(data["place2"], "Here is  some text .".into());
+    }
+
+    #[test]
+    fn typed_values_test() {
+        type rl = ParserTransfromRule;
+
+        let html = r#"
+        <div class="price">AED 1,250,000</div>
+        <div class="area">1,024.50 sqft</div>
+        <div class="verified">Yes</div>
+        <div class="listed">Listed on 01 May 2024</div>
+        <div class="beds">Studio</div>
+        "#;
+        let typed = |selector: &str, mapping: &str, value_type: ValueType| rl {
+            selector: selector.into(),
+            mapping: mapping.into(),
+            regex_sub_value: vec![r"[^\d.,]".into(), "".into()],
+            value_type: Some(value_type),
+            ..Default::default()
+        };
+        let rules = [
+            typed(".price", "Price", ValueType::Int),
+            typed(".area", "Area", ValueType::Float),
+            typed(".area", "Area Exact", ValueType::Decimal),
+            rl {
+                regex_sub_value: vec![],
+                ..typed(".verified", "Verified", ValueType::Bool)
+            },
+            rl {
+                regex_sub_value: vec![r"Listed on ".into(), "".into()],
+                date_format: "%d %B %Y".into(),
+                ..typed(".listed", "Listed", ValueType::Date)
+            },
+            typed(".beds", "Beds", ValueType::Int),
+        ];
+        let data = TransformedData::Dict(
+            transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err"),
+        );
+        let json: serde_json::Value = serde_json::from_str(&data.to_json_string()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "Price": 1250000,
+                "Area": 1024.5,
+                "Area Exact": 1024.50,
+                "Verified": true,
+                "Listed": "2024-05-01",
+                "Beds": null,
+            })
+        );
+        assert!(data.to_json_string().contains(r#""Area Exact":1024.50"#));
+
+        let strict = rl {
+            on_type_error: OnTypeError::Error,
+            ..typed(".beds", "Beds", ValueType::Int)
+        };
+        assert!(matches!(
+            transform_html_map(html, &compile_rules(&[strict]).unwrap(), &TransformSettings::default()),
+            Err(TransformError::TypeConversionError { .. })
+        ));
+
+        let rule = rl::from_str(r#"{ "selector": ".price", "mapping": "Price", "type": "decimal", "on_type_error": "error" }"#)
+            .unwrap();
+        assert_eq!(rule.value_type, Some(ValueType::Decimal));
+        assert_eq!(rule.on_type_error, OnTypeError::Error);
+    }
+
+    #[test]
+    fn filters_test() {
+        let html = r#"<a class="card" href="/property/Details-42.html"> Marina  View </a>"#;
+        let rules = [
+            ParserTransfromRule::from_str(
+                r#"{ "selector": "a.card", "mapping": "ID", "attribute_name": "href",
+                     "regex_sub_value": [".*?(\\d+).html", "\\1"],
+                     "filters": [{ "prefix": "pf-" }, "upper"] }"#,
+            )
+            .unwrap(),
+            ParserTransfromRule::from_str(
+                r#"{ "selector": "a.card", "mapping": "Link", "attribute_name": "href",
+                     "filters": [{ "split": { "separator": "/", "index": -1 } }, "lower",
+                                 { "url_join": "https://www.bayut.com/property/" }] }"#,
+            )
+            .unwrap(),
+            ParserTransfromRule::from_str(
+                r#"{ "selector": "a.card", "mapping": "Title",
+                     "filters": [{ "regex_replace": ["\\s+", " "] }, { "regex_extract": "(\\w+) Beach" }, { "default": "n/a" }] }"#,
+            )
+            .unwrap(),
+        ];
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err");
+        assert_eq!(data["ID"], "PF-42".into());
+        assert_eq!(data["Link"], "https://www.bayut.com/property/details-42.html".into());
+        assert_eq!(data["Title"], "n/a".into());
+    }
+
+    #[test]
+    fn xpath_selector_test() {
+        type Rule = ParserTransfromRule;
+
+        let html = r#"
+        <article class="card"><a href="/card/1">One</a>
+          <dl><dt>Bedrooms</dt><dd>3</dd><dt>Area</dt><dd>120 sqft</dd></dl></article>
+        <article class="card"><a href="/card/2">Two</a>
+          <dl><dt>Area</dt><dd>95 sqft</dd></dl></article>
+        "#;
+        let xpath = |selector: &str, mapping: &str| Rule {
+            selector: selector.into(),
+            selector_type: SelectorType::Xpath,
+            mapping: mapping.into(),
+            ..Default::default()
+        };
+        let rules = [
+            Rule {
+                selector: "article.card".into(),
+                grouping: "cards".into(),
+                children: vec![
+                    Rule {
+                        attribute_name: "href".into(),
+                        ..xpath("./a", "url")
+                    },
+                    xpath(".//dt[.='Bedrooms']/following-sibling::dd[1]", "Bedrooms"),
+                    Rule {
+                        selector: "dl".into(),
+                        children: vec![xpath("dt[.='Area']/following-sibling::dd[1]", "Area")].into(),
+                        ..Default::default()
+                    },
+                ]
+                .into(),
+                ..Default::default()
+            },
+            xpath("//article[a='Two']//dd", "second_area"),
+        ];
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err");
+        let cards = data["cards"].exract_list();
+        assert_eq!(cards.len(), 2);
+        let first = cards[0].exract_dict();
+        assert_eq!(first["url"], "/card/1".into());
+        assert_eq!(first["Bedrooms"], "3".into());
+        assert_eq!(first["Area"], "120 sqft".into());
+        let second = cards[1].exract_dict();
+        assert!(!second.contains_key("Bedrooms"));
+        assert_eq!(second["Area"], "95 sqft".into());
+        assert_eq!(data["second_area"], "95 sqft".into());
+
+        let rule = Rule::from_str(r#"{ "selector": "//dd[", "selector_type": "xpath", "mapping": "x" }"#).unwrap();
+        assert!(matches!(CompiledRule::compile(&rule), Err(TransformError::SelectorError { .. })));
+    }
+
+    #[test]
+    fn json_rules_test() {
+        type Rule = ParserTransfromRule;
+
+        let html = r#"
+        <script type="application/ld+json">
+          {"@type": "Residence", "name": "Marina View",
+           "geo": {"@type": "GeoCoordinates", "latitude": 25.0712, "longitude": 55.1389},
+           "amenities": [{"name": "Pool"}, {"name": "Gym"}], "agent": null}
+        </script>
+        <script id="__NEXT_DATA__">window.__NEXT_DATA__ = {"props": {"id": 42}};</script>
+        <script id="broken">{"props": </script>
+        "#;
+        let jsonpath = |selector: &str, mapping: &str| Rule {
+            selector: selector.into(),
+            selector_type: SelectorType::Jsonpath,
+            mapping: mapping.into(),
+            ..Default::default()
+        };
+        let rules = [
+            Rule {
+                selector: r#"script[type="application/ld+json"]"#.into(),
+                parse_as: ParseAs::Json,
+                children: vec![
+                    Rule {
+                        value_type: Some(ValueType::Float),
+                        ..jsonpath("$..geo.latitude", "Coords Lat")
+                    },
+                    jsonpath("$..geo.longitude", "Coords Lng"),
+                    jsonpath("$.name", "Title"),
+                    jsonpath("$.agent", "Agent"),
+                    jsonpath("$.missing", "Missing"),
+                    Rule {
+                        selector: "$.amenities[*]".into(),
+                        selector_type: SelectorType::Jsonpath,
+                        grouping: "Amenities".into(),
+                        children: vec![jsonpath("$.name", "name")].into(),
+                        ..Default::default()
+                    },
+                    jsonpath("$.geo", "Geo"),
+                ]
+                .into(),
+                ..Default::default()
+            },
+            Rule {
+                selector: "script#__NEXT_DATA__".into(),
+                parse_as: ParseAs::Json,
+                filters: vec![ValueFilter::RegexExtract(r"=\s*(\{.*\});?$".into())],
+                children: vec![jsonpath("$.props.id", "ID")].into(),
+                ..Default::default()
+            },
+            Rule {
+                selector: "script#broken".into(),
+                parse_as: ParseAs::Json,
+                children: vec![jsonpath("$.props", "Broken")].into(),
+                ..Default::default()
+            },
+        ];
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).expect("Err");
+        assert_eq!(data["Coords Lat"], TransformedData::Float(25.0712));
+        assert_eq!(data["Coords Lng"], "55.1389".into());
+        assert_eq!(data["Title"], "Marina View".into());
+        assert_eq!(data["Agent"], "".into());
+        assert!(!data.contains_key("Missing"));
+        assert!(!data.contains_key("Broken"));
+        let amenities: Vec<&TransformedData> =
+            data["Amenities"].exract_list().iter().map(|item| &item.exract_dict()["name"]).collect();
+        assert_eq!(amenities, vec![&"Pool".into(), &"Gym".into()]);
+        assert!(data["Geo"].exract_value().contains(r#""latitude":25.0712"#));
+        assert_eq!(data["ID"], "42".into());
+
+        let strict = Rule {
+            exception_on_not_found: true,
+            ..rules[2].clone()
+        };
+        assert!(matches!(
+            transform_html_map(html, &compile_rules(&[strict]).unwrap(), &TransformSettings::default()),
+            Err(TransformError::JsonError { .. })
+        ));
+        // jsonpath only below parse_as json, css never below it
+        assert!(compile_rules(&[jsonpath("$.name", "Title")]).is_err());
+        let css_in_json = Rule {
+            children: vec![Rule {
+                selector: "div".into(),
+                mapping: "x".into(),
+                ..Default::default()
+            }]
+            .into(),
+            ..rules[1].clone()
+        };
+        assert!(compile_rules(&[css_in_json]).is_err());
+    }
+
+    #[test]
+    fn captures_test() {
+        let address_rule = |on_no_match: &str| -> ParserTransfromRule {
+            serde_yaml::from_str(&format!(
+                r#"
+selector: p.address
+mapping: Address
+regex: '^(?:(?:.*,\s*)?(?P<district>[^,]+),\s*)?(?P<city>[^,]+)$'
+captures:
+  city: City
+  district: Location.District
+on_no_match: {on_no_match}
+"#
+            ))
+            .unwrap()
+        };
+        let extract = |address: &str, on_no_match: &str| {
+            let html = format!(r#"<p class="address">{address}</p>"#);
+            transform_html_map(&html, &compile_rules(&[address_rule(on_no_match)]).unwrap(), &TransformSettings::default())
+                .map(|data| TransformedData::Dict(data).to_json_string())
+        };
+        let html = r#"<p class="address">Marina Gate 1, Dubai Marina, Dubai</p>"#;
+        assert_eq!(
+            transform_html_map(html, &compile_rules(&[address_rule("skip")]).unwrap(), &TransformSettings::default())
+                .unwrap(),
+            data_map(&[
+                ("Address", "Marina Gate 1, Dubai Marina, Dubai"),
+                ("City", "Dubai"),
+                ("Location.District", "Dubai Marina"),
+            ])
+        );
+        let html = r#"<p class="address">Dubai Marina, Dubai</p>"#;
+        assert_eq!(
+            transform_html_map(html, &compile_rules(&[address_rule("skip")]).unwrap(), &TransformSettings::default())
+                .unwrap(),
+            data_map(&[("Address", "Dubai Marina, Dubai"), ("City", "Dubai"), ("Location.District", "Dubai Marina")])
+        );
+        assert!(!extract("Dubai", "skip").unwrap().contains("District"));
+        assert!(extract("Dubai", "null").unwrap().contains(r#""District":null"#));
+        assert!(extract("Dubai", "error").unwrap().contains(r#""City":"Dubai""#));
+        assert!(!extract(", UAE", "skip").unwrap().contains("City"));
+        assert!(extract(", UAE", "null").unwrap().contains(r#""City":null"#));
+        assert!(matches!(extract(", UAE", "error"), Err(TransformError::NoMatchError { .. })));
+
+        let mut rule = address_rule("skip");
+        rule.captures.insert("street".into(), "Street".into());
+        assert!(matches!(CompiledRule::compile(&rule), Err(TransformError::RegexError { .. })));
+        rule.captures.clear();
+        assert!(matches!(CompiledRule::compile(&rule), Err(TransformError::RegexError { .. })));
+    }
+
+    #[test]
+    fn fallback_selectors_test() {
+        let rules: Vec<ParserTransfromRule> = serde_yaml::from_str(
+            r#"
+- selector: span.price-new
+  selectors: [span.price, div.price]
+  mapping: Price
+  type: int
+- selector: .agent
+  mapping: Agent
+  default: unknown
+  exception_on_not_found: true
+- selectors: [.beds, .bedrooms]
+  mapping: Beds
+  type: int
+  default: '0'
+- selector: h1
+  mapping: Title
+- selector: script
+  parse_as: json
+  children:
+    - selector: $.geo.lat
+      selectors: [$.latitude]
+      selector_type: jsonpath
+      mapping: Lat
+"#,
+        )
+        .unwrap();
+        let html = r#"<h1>Villa</h1><div class="price">1,200</div><span class="price">900</span>
+            <script>{"latitude": "25.1"}</script>"#;
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).unwrap();
+        assert_eq!(data["Price"], TransformedData::Int(900));
+        assert_eq!(data["Agent"], TransformedData::Value("unknown".into()));
+        assert_eq!(data["Beds"], TransformedData::Int(0));
+        assert_eq!(data["Title"], TransformedData::Value("Villa".into()));
+        assert_eq!(data["Lat"], TransformedData::Value("25.1".into()));
+        let TransformedData::Dict(matched) = &data[MATCHED_SELECTORS_KEY] else {
+            panic!("{MATCHED_SELECTORS_KEY} isn't a dict");
+        };
+        assert_eq!(matched["Price"], TransformedData::Value("span.price".into()));
+        assert_eq!(matched["Agent"], TransformedData::Null);
+        assert_eq!(matched["Beds"], TransformedData::Null);
+        assert_eq!(matched["Lat"], TransformedData::Value("$.latitude".into()));
+        assert!(!matched.contains_key("Title"));
+
+        let mut rule = rules[0].clone();
+        rule.selectors.push("li[".into());
+        assert!(matches!(CompiledRule::compile(&rule), Err(TransformError::SelectorError { .. })));
+    }
+
+    #[test]
+    fn extract_targets_test() {
+        let rules: Vec<ParserTransfromRule> = serde_yaml::from_str(
+            r#"
+- selector: div.description
+  mapping: Description
+  extract: inner_html
+- selector: div.description
+  mapping: Lead
+  extract: { text_node: 0 }
+- selector: img
+  mapping: Images
+  extract: srcset
+  attribute_name: data-srcset
+  filters:
+    - url_join: 'https://www.bayut.com'
+- selector: img
+  mapping: Data
+  extract: data
+"#,
+        )
+        .unwrap();
+        let html = r#"<div class="description"> Sea view <br><b>Vacant</b> </div>
+            <img data-srcset="/a-1.jpg 1x, /a-2.jpg 2x" data-id="5">"#;
+        let data = transform_html_map(html, &compile_rules(&rules).unwrap(), &TransformSettings::default()).unwrap();
+        assert_eq!(data["Description"], TransformedData::Value("Sea view <br><b>Vacant</b>".into()));
+        assert_eq!(data["Lead"], TransformedData::Value("Sea view".into()));
+        assert_eq!(
+            data["Images"],
+            TransformedData::Dict(data_map(&[
+                ("1x", "https://www.bayut.com/a-1.jpg"),
+                ("2x", "https://www.bayut.com/a-2.jpg"),
+            ]))
+        );
+        assert_eq!(data["Data"], TransformedData::Dict(data_map(&[("id", "5"), ("srcset", "/a-1.jpg 1x, /a-2.jpg 2x")])));
+
+        let mut rule = rules[3].clone();
+        rule.attribute_name = "src".into();
+        assert!(matches!(CompiledRule::compile(&rule), Err(TransformError::SelectorError { .. })));
+    }
+
+    #[test]
+    fn normalization_test() {
+        let rules: Vec<ParserTransfromRule> = serde_yaml::from_str(
+            r#"
+- selector: p.area
+  mapping: Area
+- selector: p.area
+  mapping: Parts
+  normalize: { join_separator: '|', collapse_whitespace: false, case_fold: true }
+"#,
+        )
+        .unwrap();
+        let rules = compile_rules(&rules).unwrap();
+        let html = "<p class=\"area\">1,200\u{00A0}\u{200B}<b>SQ  FT</b></p>";
+        let data = transform_html_map(html, &rules, &TransformSettings::default()).unwrap();
+        assert_eq!(data["Area"], TransformedData::Value("1,200\u{00A0}\u{200B} SQ  FT".into()));
+
+        let settings = TransformSettings {
+            normalize: serde_yaml::from_str("{ collapse_whitespace: true, strip_invisible: true }").unwrap(),
+            ..Default::default()
+        };
+        let data = transform_html_map(html, &rules, &settings).unwrap();
+        assert_eq!(data["Area"], TransformedData::Value("1,200 SQ FT".into()));
+        assert_eq!(data["Parts"], TransformedData::Value("1,200\u{00A0}|sq  ft".into()));
+    }
+
+    #[test]
+    fn pick_test() {
+        let rules: Vec<ParserTransfromRule> = serde_yaml::from_str(
+            r#"
+- selector: li
+  mapping: First
+  pick: first
+- selector: li
+  mapping: Last
+  pick: last
+  skip: 1
+- selector: li
+  mapping: Second
+  pick: nth:1
+- selector: li
+  mapping: All
+  pick: all
+  type: int
+- selector: li
+  mapping: Unique
+  pick: unique
+  limit: 3
+- selector: li
+  mapping: Joined
+  pick: 'join:, '
+- selector: span
+  mapping: None
+  pick: all
+- selector: li
+  grouping: Items
+  pick: all
+  children:
+    - mapping: Item
+"#,
+        )
+        .unwrap();
+        let rules = compile_rules(&rules).unwrap();
+        let list = |values: Vec<TransformedData>| TransformedData::List(Box::new(values));
+        let data = transform_html_map("<ul><li>1</li><li>2</li><li>1</li></ul>", &rules, &TransformSettings::default()).unwrap();
+        assert_eq!(data["First"], TransformedData::Value("1".into()));
+        assert_eq!(data["Last"], TransformedData::Value("1".into()));
+        assert_eq!(data["Second"], TransformedData::Value("2".into()));
+        assert_eq!(data["All"], list(vec![TransformedData::Int(1), TransformedData::Int(2), TransformedData::Int(1)]));
+        assert_eq!(data["Unique"], list(vec!["1".into(), "2".into()]));
+        assert_eq!(data["Joined"], TransformedData::Value("1, 2, 1".into()));
+        assert_eq!(data["None"], list(vec![]));
+        assert_eq!(data["Items"].exract_list().len(), 3);
+
+        let data = transform_html_map("<ul><li>7</li></ul>", &rules, &TransformSettings::default()).unwrap();
+        assert!(!data.contains_key("Last"));
+        assert_eq!(data["All"], list(vec![TransformedData::Int(7)]));
+        assert_eq!(data["Joined"], TransformedData::Value("7".into()));
+        assert_eq!(data["Items"].exract_list().len(), 1);
+    }
+
+    /// Dict with nested dicts for dotted keys like `push_value_path`
+    fn data_map(values: &[(&str, &str)]) -> DataMap {
+        let mut data = TransformedData::create_dict();
+        for (path, value) in values {
+            data.push_value_path(path, (*value).into());
+        }
+        match data {
+            TransformedData::Dict(dict) => dict,
+            _ => unreachable!(),
+        }
     }
 
     #[test]
@@ -542,7 +1119,7 @@ path/to/baz:3:It's a Trap!
             .into(),
             ..Default::default()
         }];
-        let data = transform_html(&html, &rules, &TransformSettings::default()).expect("Err");
+        let data = transform_html(&html, &compile_rules(&rules)?, &TransformSettings::default()).expect("Err");
 
         info!("data = [\n{}\n]", data.to_json_string());
 
@@ -566,7 +1143,7 @@ path/to/baz:3:It's a Trap!
         //     .into(),
         //     ..Default::default()
         // }];
-        // let data = transform_html(&html, &rules, &TransformSettings::default()).expect("Err");
+        // let data = transform_html(&html, &compile_rules(&rules)?, &TransformSettings::default()).expect("Err");
 
         // info!("data = [{data:#?}]");
         Ok(())
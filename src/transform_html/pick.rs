@@ -0,0 +1,137 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// `pick` of a rule, the shape of the output whatever the number of matched elements,
+/// without it one match is a value and several matches are handled one by one
+///
+/// ```yaml
+/// pick: first
+/// pick: nth:-1
+/// pick: 'join:, '
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(try_from = "String", into = "String")]
+pub enum Pick {
+    First,
+    Last,
+    /// by index, a negative index counts from the end
+    Nth(i64),
+    /// values of every element as a list, every element is handled by children otherwise
+    All,
+    /// filtered texts of every element joined with the separator, empty texts are dropped
+    Join(String),
+    /// `all` without repeated values
+    Unique,
+}
+
+impl TryFrom<String> for Pick {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "first" => return Ok(Pick::First),
+            "last" => return Ok(Pick::Last),
+            "all" => return Ok(Pick::All),
+            "unique" => return Ok(Pick::Unique),
+            _ => {}
+        }
+        if let Some(separator) = value.strip_prefix("join:") {
+            return Ok(Pick::Join(separator.to_owned()));
+        }
+        value
+            .strip_prefix("nth:")
+            .and_then(|index| index.trim().parse().ok())
+            .map(Pick::Nth)
+            .ok_or_else(|| format!("pick [{value}] isn't one of first, last, nth:N, all, join:SEP, unique"))
+    }
+}
+
+impl fmt::Display for Pick {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pick::First => write!(f, "first"),
+            Pick::Last => write!(f, "last"),
+            Pick::Nth(index) => write!(f, "nth:{index}"),
+            Pick::All => write!(f, "all"),
+            Pick::Join(separator) => write!(f, "join:{separator}"),
+            Pick::Unique => write!(f, "unique"),
+        }
+    }
+}
+
+impl From<Pick> for String {
+    fn from(pick: Pick) -> Self {
+        pick.to_string()
+    }
+}
+
+impl Pick {
+    /// True for picks which write a list
+    pub fn is_list(&self) -> bool {
+        matches!(self, Pick::All | Pick::Unique)
+    }
+
+    /// Problem of the pick with other fields of the rule
+    pub fn conflict(&self, has_mapping: bool, has_children: bool, has_captures: bool, dict_extract: bool) -> Option<&'static str> {
+        match self {
+            Pick::All | Pick::Unique if has_captures => Some("`captures` need a pick of one value"),
+            Pick::All if has_mapping && has_children => Some("`pick: all` needs either `mapping` or `children`"),
+            Pick::Unique | Pick::Join(_) if has_children => Some("`pick: unique` and `pick: join` don't work with `children`"),
+            Pick::Join(_) if dict_extract => Some("`pick: join` needs a text `extract`"),
+            _ => None,
+        }
+    }
+
+    /// The element of `first`, `last` and `nth`, every element for other picks
+    pub fn select<T>(&self, items: Vec<T>) -> Vec<T> {
+        let index = match self {
+            Pick::First => Some(0),
+            Pick::Last => items.len().checked_sub(1),
+            Pick::Nth(index) if *index < 0 => usize::try_from(index.unsigned_abs())
+                .ok()
+                .and_then(|from_end| items.len().checked_sub(from_end)),
+            Pick::Nth(index) => usize::try_from(*index).ok(),
+            _ => return items,
+        };
+        match index {
+            Some(index) => items.into_iter().nth(index).into_iter().collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick() {
+        let picks: Vec<Pick> = serde_yaml::from_str("[first, last, 'nth:-2', all, 'join:, ', 'join:', unique]").unwrap();
+        assert_eq!(
+            picks,
+            vec![
+                Pick::First,
+                Pick::Last,
+                Pick::Nth(-2),
+                Pick::All,
+                Pick::Join(", ".into()),
+                Pick::Join("".into()),
+                Pick::Unique
+            ]
+        );
+        assert_eq!(serde_yaml::to_string(&Pick::Nth(3)).unwrap().trim(), "nth:3");
+        assert!(serde_yaml::from_str::<Pick>("nth:x").unwrap_err().to_string().contains("isn't one of"));
+        assert!(serde_yaml::from_str::<Pick>("second").is_err());
+
+        let items = || vec![1, 2, 3];
+        assert_eq!(Pick::First.select(items()), vec![1]);
+        assert_eq!(Pick::Last.select(items()), vec![3]);
+        assert_eq!(Pick::Nth(1).select(items()), vec![2]);
+        assert_eq!(Pick::Nth(-3).select(items()), vec![1]);
+        assert_eq!(Pick::Nth(3).select(items()), Vec::<i32>::new());
+        assert_eq!(Pick::Nth(-4).select(items()), Vec::<i32>::new());
+        assert_eq!(Pick::Last.select(Vec::<i32>::new()), Vec::<i32>::new());
+        assert_eq!(Pick::All.select(items()), items());
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::{HashMap, HashSet};
+
+use ego_tree::NodeId;
+use scraper::{ElementRef, Selector};
+
+use super::defs::TransformError;
+
+const PSEUDO: &str = ":-soup-contains";
+const CONTAINS_OWN: &str = ":-soup-contains-own(";
+const CONTAINS: &str = ":-soup-contains(";
+
+/// `:-soup-contains("a", "b")` matches when any text is a substring of the element text,
+/// `-own` looks only at text nodes which are direct children
+#[derive(Debug, Clone, PartialEq)]
+struct ContainsClause {
+    texts: Vec<String>,
+    own: bool,
+}
+
+impl ContainsClause {
+    fn matches(&self, ele: &ElementRef) -> bool {
+        let text: String = if self.own {
+            ele.children()
+                .filter_map(|child| child.value().as_text())
+                .map(|text| &**text)
+                .collect()
+        } else {
+            ele.text().collect()
+        };
+        self.texts.iter().any(|t| text.contains(t.as_str()))
+    }
+}
+
+/// Css part up to a compound with contains clauses, later segments are relative to
+/// the element matched by the previous one and start with a combinator
+#[derive(Debug, Clone)]
+struct Segment {
+    css: String,
+    /// None for `+` or `~` segments, they are selected from the parent of the previous element
+    selector: Option<Selector>,
+    contains: Vec<ContainsClause>,
+}
+
+/// BeautifulSoup (soupsieve) selector with `:-soup-contains` and `:-soup-contains-own`
+/// pseudo-classes, any amount of them per compound and per selector list
+#[derive(Debug, Clone)]
+pub struct SoupSelector {
+    alternatives: Vec<Vec<Segment>>,
+}
+
+fn selector_error(selector: &str, message: impl ToString) -> TransformError {
+    TransformError::SelectorError {
+        selector: selector.to_owned(),
+        message: message.to_string(),
+    }
+}
+
+fn parse_css(css: &str) -> Result<Selector, TransformError> {
+    Selector::parse(css).map_err(|err| selector_error(css, format!("{err:?}")))
+}
+
+/// Splits on `sep` outside of quotes, brackets and parentheses
+fn split_top_level(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quote, mut escaped, mut begin) = (0i32, None, false, 0);
+    for (idx, ch) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, ch) {
+            (_, '\\') => escaped = true,
+            (Some(q), _) if ch == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '(' | '[') => depth += 1,
+            (None, ')' | ']') => depth -= 1,
+            (None, _) if ch == sep && depth == 0 => {
+                parts.push(&text[begin..idx]);
+                begin = idx + ch.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[begin..]);
+    parts
+}
+
+/// Byte index of the parenthesis closing the one right before `text`
+fn closing_paren(text: &str) -> Option<usize> {
+    let (mut depth, mut quote, mut escaped) = (0i32, None, false);
+    for (idx, ch) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, ch) {
+            (_, '\\') => escaped = true,
+            (Some(q), _) if ch == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(ch),
+            (None, '(') => depth += 1,
+            (None, ')') if depth == 0 => return Some(idx),
+            (None, ')') => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Quoted strings with backslash escapes or bare words
+fn parse_texts(args: &str) -> Vec<String> {
+    split_top_level(args, ',')
+        .into_iter()
+        .map(|arg| {
+            let arg = arg.trim();
+            let quoted = arg.len() >= 2
+                && (arg.starts_with('"') && arg.ends_with('"')
+                    || arg.starts_with('\'') && arg.ends_with('\''));
+            if !quoted {
+                return arg.to_owned();
+            }
+            let mut text = String::new();
+            let mut chars = arg[1..arg.len() - 1].chars();
+            while let Some(ch) = chars.next() {
+                match ch {
+                    '\\' => text.extend(chars.next()),
+                    _ => text.push(ch),
+                }
+            }
+            text
+        })
+        .collect()
+}
+
+impl Segment {
+    fn create(css: &str, is_first: bool, contains: Vec<ContainsClause>) -> Result<Self, TransformError> {
+        let mut css = css.trim_start().to_owned();
+        // a compound made only of contains clauses
+        let only_contains = css.is_empty() || css.ends_with(['>', '+', '~']) || css.ends_with(char::is_whitespace);
+        if !contains.is_empty() && only_contains {
+            css.push('*');
+        }
+        if is_first {
+            let selector = parse_css(&css)?;
+            return Ok(Self { css, selector: Some(selector), contains });
+        }
+        let css = css.trim().to_owned();
+        let selector = if css.starts_with(['+', '~']) {
+            None
+        } else {
+            Some(parse_css(&format!(":scope {css}"))?)
+        };
+        Ok(Self { css, selector, contains })
+    }
+
+    fn select<'a>(&self, ele: ElementRef<'a>) -> Vec<ElementRef<'a>> {
+        let tags: Vec<ElementRef<'a>> = match &self.selector {
+            Some(selector) => ele.select(selector).collect(),
+            None => {
+                let Some(parent) = ele.parent().and_then(ElementRef::wrap) else {
+                    return Vec::new();
+                };
+                let position = ele.prev_siblings().filter(|s| s.value().is_element()).count() + 1;
+                match Selector::parse(&format!(":scope > :nth-child({position}) {}", self.css)) {
+                    Ok(selector) => parent.select(&selector).collect(),
+                    Err(_) => Vec::new(),
+                }
+            }
+        };
+        tags.into_iter()
+            .filter(|tag| self.contains.iter().all(|clause| clause.matches(tag)))
+            .collect()
+    }
+}
+
+impl SoupSelector {
+    /// True for selectors which need this parser instead of `scraper::Selector`
+    pub fn has_contains(selector: &str) -> bool {
+        selector.contains(PSEUDO)
+    }
+
+    pub fn parse(selector: &str) -> Result<Self, TransformError> {
+        let alternatives = split_top_level(selector, ',')
+            .into_iter()
+            .map(|alternative| Self::parse_alternative(selector, alternative))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { alternatives })
+    }
+
+    fn parse_alternative(selector: &str, alternative: &str) -> Result<Vec<Segment>, TransformError> {
+        let mut segments = Vec::new();
+        let mut css = String::new();
+        let mut clauses: Vec<ContainsClause> = Vec::new();
+        let mut rest = alternative.trim();
+
+        while let Some(ch) = rest.chars().next() {
+            let own = rest.starts_with(CONTAINS_OWN);
+            if own || rest.starts_with(CONTAINS) {
+                let args = &rest[if own { CONTAINS_OWN.len() } else { CONTAINS.len() }..];
+                let end = closing_paren(args)
+                    .ok_or_else(|| selector_error(selector, "unclosed :-soup-contains"))?;
+                clauses.push(ContainsClause { texts: parse_texts(&args[..end]), own });
+                rest = &args[end + 1..];
+                continue;
+            }
+            if !clauses.is_empty() && (ch.is_whitespace() || matches!(ch, '>' | '+' | '~')) {
+                segments.push(Segment::create(&css, segments.is_empty(), std::mem::take(&mut clauses))?);
+                css.clear();
+            }
+            let len = match ch {
+                '(' | '[' | '"' | '\'' => {
+                    // copy nested parts as is, contains inside of them isn't supported
+                    let end = match ch {
+                        '(' => closing_paren(&rest[1..]).map(|end| end + 2),
+                        '[' => rest.find(']').map(|end| end + 1),
+                        _ => rest[1..].find(ch).map(|end| end + 2),
+                    }
+                    .ok_or_else(|| selector_error(selector, format!("unclosed [{ch}]")))?;
+                    if rest[..end].contains(PSEUDO) {
+                        return Err(selector_error(selector, "nested :-soup-contains isn't supported"));
+                    }
+                    end
+                }
+                _ => ch.len_utf8(),
+            };
+            css.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+        if !clauses.is_empty() || !css.trim().is_empty() || segments.is_empty() {
+            segments.push(Segment::create(&css, segments.is_empty(), clauses)?);
+        }
+        Ok(segments)
+    }
+
+    /// Matching descendants of the element in document order
+    pub fn select<'a>(&self, soup: ElementRef<'a>) -> Vec<ElementRef<'a>> {
+        let mut found: Vec<ElementRef<'a>> = Vec::new();
+        let mut seen: HashSet<NodeId> = HashSet::new();
+        for segments in self.alternatives.iter() {
+            let mut current = vec![soup];
+            for segment in segments {
+                let mut next_seen = HashSet::new();
+                current = current
+                    .into_iter()
+                    .flat_map(|ele| segment.select(ele))
+                    .filter(|ele| next_seen.insert(ele.id()))
+                    .collect();
+            }
+            found.extend(current.into_iter().filter(|ele| seen.insert(ele.id())));
+        }
+        if self.alternatives.len() > 1 || self.alternatives.iter().any(|s| s.len() > 1) {
+            let order: HashMap<NodeId, usize> = soup
+                .descendants()
+                .enumerate()
+                .map(|(idx, node)| (node.id(), idx))
+                .collect();
+            found.sort_by_key(|ele| order.get(&ele.id()).copied().unwrap_or(usize::MAX));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use proptest::prelude::*;
+    use scraper::Html;
+
+    use super::*;
+
+    fn ids(tags: &[ElementRef]) -> Vec<NodeId> {
+        tags.iter().map(|tag| tag.id()).collect()
+    }
+
+    fn select_html(html: &Html, selector: &str) -> Vec<String> {
+        SoupSelector::parse(selector)
+            .unwrap()
+            .select(html.root_element())
+            .iter()
+            .map(|tag| tag.text().collect::<String>())
+            .collect()
+    }
+
+    #[test]
+    fn test_soup_contains() {
+        let html = Html::parse_document(
+            r#"
+            <ul>
+              <li class="fact">Property type: <b>Villa</b></li>
+              <li class="fact">Bedrooms: <b>4</b><i>rooms</i></li>
+              <li class="fact"><span>Bathrooms:</span> <b>3</b></li>
+            </ul>
+            <div>Here is <span>some text</span>.</div>
+            "#,
+        );
+        assert_eq!(select_html(&html, r#"li.fact:-soup-contains("Property type:") b"#), vec!["Villa"]);
+        // substring over several text nodes
+        assert_eq!(select_html(&html, r#"div:-soup-contains("is some") span"#), vec!["some text"]);
+        assert_eq!(select_html(&html, r#"li:-soup-contains-own("Bathrooms:")"#), Vec::<String>::new());
+        assert_eq!(select_html(&html, r#"li:-soup-contains-own('Bedrooms', "Property") > b"#), vec!["Villa", "4"]);
+        assert_eq!(
+            select_html(&html, r#"li:-soup-contains("4"):-soup-contains("rooms") b + i"#),
+            vec!["rooms"]
+        );
+        assert_eq!(
+            select_html(&html, r#"span:-soup-contains("Bath") ~ b, li:-soup-contains("Villa") b, div span"#),
+            vec!["Villa", "3", "some text"]
+        );
+        assert_eq!(select_html(&html, r#"ul :-soup-contains("Villa")"#), vec!["Property type: Villa", "Villa"]);
+        assert_eq!(
+            select_html(&html, r#"li:-soup-contains("a, \"b\"", Bed)"#),
+            vec!["Bedrooms: 4rooms"]
+        );
+        assert!(SoupSelector::parse(r#"li:not(:-soup-contains("x"))"#).is_err());
+        assert!(SoupSelector::parse(r#"li:-soup-contains("x""#).is_err());
+        assert!(SoupSelector::parse(r#"li:-soup-contains("x") >"#).is_err());
+    }
+
+    fn fixture() -> Html {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("resources/test/property-finder.frament.html");
+        Html::parse_document(&fs::read_to_string(path).unwrap())
+    }
+
+    fn own_text(ele: &ElementRef) -> String {
+        ele.children()
+            .filter_map(|child| child.value().as_text())
+            .map(|text| &**text)
+            .collect()
+    }
+
+    fn element_text(ele: &ElementRef, own: bool) -> String {
+        if own {
+            own_text(ele)
+        } else {
+            ele.text().collect()
+        }
+    }
+
+    /// Substring of the text of a fixture element matched by the base selector
+    fn pick_text(html: &Html, base: &str, idx: usize, start: usize, len: usize, own: bool) -> Option<String> {
+        let tags: Vec<ElementRef> = html.select(&Selector::parse(base).unwrap()).collect();
+        let text: Vec<char> = element_text(tags.get(idx % tags.len().max(1))?, own).chars().collect();
+        if text.is_empty() {
+            return None;
+        }
+        let start = start % text.len();
+        let text: String = text[start..(start + len).min(text.len())].iter().collect();
+        (!text.contains(['"', '\\'])).then_some(text)
+    }
+
+    const BASES: [&str; 6] = ["li", "div", "span", "article", "p", "a"];
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_single_contains(base in 0..BASES.len(), idx in 0..64usize, start in 0..200usize, len in 1..12usize, own: bool) {
+            let html = fixture();
+            let base = BASES[base];
+            let Some(text) = pick_text(&html, base, idx, start, len, own) else {
+                return Ok(());
+            };
+            let pseudo = if own { "-soup-contains-own" } else { "-soup-contains" };
+            let selector = SoupSelector::parse(&format!("{base}:{pseudo}(\"{text}\")")).unwrap();
+
+            let expected: Vec<ElementRef> = html
+                .root_element()
+                .select(&Selector::parse(base).unwrap())
+                .filter(|ele| element_text(ele, own).contains(&text))
+                .collect();
+            prop_assert!(!expected.is_empty());
+            prop_assert_eq!(ids(&selector.select(html.root_element())), ids(&expected));
+        }
+
+        #[test]
+        fn prop_compound_contains(
+            first in 0..BASES.len(),
+            second in 0..BASES.len(),
+            idx in 0..64usize,
+            start in 0..200usize,
+            len in 1..8usize,
+        ) {
+            let html = fixture();
+            let (first, second) = (BASES[first], BASES[second]);
+            let (Some(text), Some(own)) = (
+                pick_text(&html, first, idx, start, len, false),
+                pick_text(&html, second, idx, start / 2, len, true),
+            ) else {
+                return Ok(());
+            };
+            let selector = SoupSelector::parse(&format!(
+                "{first}:-soup-contains(\"{text}\"):-soup-contains(\"{half}\") span, {second}:-soup-contains-own(\"{own}\")",
+                half = text.chars().take(len / 2).collect::<String>()
+            ))
+            .unwrap();
+
+            let root = html.root_element();
+            let order: Vec<NodeId> = root.descendants().map(|node| node.id()).collect();
+            let span = Selector::parse("span").unwrap();
+            let mut expected: Vec<ElementRef> = root
+                .select(&Selector::parse(first).unwrap())
+                .filter(|ele| ele.text().collect::<String>().contains(&text))
+                .flat_map(|ele| ele.select(&span).collect::<Vec<_>>())
+                .chain(
+                    root.select(&Selector::parse(second).unwrap())
+                        .filter(|ele| own_text(ele).contains(&own)),
+                )
+                .collect();
+            expected.sort_by_key(|ele| order.iter().position(|id| *id == ele.id()));
+            expected.dedup_by_key(|ele| ele.id());
+            prop_assert_eq!(ids(&selector.select(root)), ids(&expected));
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// `normalize` of `TransformSettings`, cleaning of extracted text before `regex_sub_value`,
+/// the defaults keep the text as is
+///
+/// ```yaml
+/// normalize:
+///   collapse_whitespace: true
+///   strip_invisible: true
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TextNormalization {
+    /// between text nodes of an element
+    pub join_separator: String,
+    /// runs of whitespace including non breaking spaces become one space
+    pub collapse_whitespace: bool,
+    /// Unicode NFKC, `１２ m²` is `12 m2`
+    pub nfkc: bool,
+    /// HTML entities left in the text like `&amp;nbsp;`
+    pub decode_entities: bool,
+    /// zero width, bidi and other format characters, soft hyphens
+    pub strip_invisible: bool,
+    /// lower case
+    pub case_fold: bool,
+}
+
+impl Default for TextNormalization {
+    fn default() -> Self {
+        Self {
+            join_separator: " ".into(),
+            collapse_whitespace: false,
+            nfkc: false,
+            decode_entities: false,
+            strip_invisible: false,
+            case_fold: false,
+        }
+    }
+}
+
+/// `normalize` of a rule, set options replace ones of `TransformSettings`
+#[derive(Debug, Default, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct TextNormalizationOverride {
+    pub join_separator: Option<String>,
+    pub collapse_whitespace: Option<bool>,
+    pub nfkc: Option<bool>,
+    pub decode_entities: Option<bool>,
+    pub strip_invisible: Option<bool>,
+    pub case_fold: Option<bool>,
+}
+
+impl TextNormalizationOverride {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+fn is_invisible(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{00AD}' | '\u{180E}' | '\u{200B}'..='\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}' | '\u{FEFF}'
+    )
+}
+
+impl TextNormalization {
+    pub fn with_override(&self, rule: &TextNormalizationOverride) -> Cow<'_, Self> {
+        if rule.is_empty() {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(Self {
+            join_separator: rule.join_separator.clone().unwrap_or_else(|| self.join_separator.clone()),
+            collapse_whitespace: rule.collapse_whitespace.unwrap_or(self.collapse_whitespace),
+            nfkc: rule.nfkc.unwrap_or(self.nfkc),
+            decode_entities: rule.decode_entities.unwrap_or(self.decode_entities),
+            strip_invisible: rule.strip_invisible.unwrap_or(self.strip_invisible),
+            case_fold: rule.case_fold.unwrap_or(self.case_fold),
+        })
+    }
+
+    /// Entities, NFKC, invisible characters, whitespace and case in this order
+    pub fn apply(&self, text: String) -> String {
+        let mut text = text;
+        if self.decode_entities {
+            text = html_escape::decode_html_entities(&text).into_owned();
+        }
+        if self.nfkc {
+            text = text.nfkc().collect();
+        }
+        if self.strip_invisible {
+            text.retain(|ch| !is_invisible(ch));
+        }
+        if self.collapse_whitespace {
+            text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        if self.case_fold {
+            text = text.to_lowercase();
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization() {
+        let text = "  Dubai\u{00A0}\u{00A0}Marina,\u{200B}\n\t ＪＢＲ &amp;nbsp;１２ m² ";
+        let settings = TextNormalization::default();
+        assert_eq!(settings.apply(text.to_owned()), text);
+        assert!(matches!(settings.with_override(&Default::default()), Cow::Borrowed(_)));
+
+        let settings = TextNormalization {
+            collapse_whitespace: true,
+            strip_invisible: true,
+            ..Default::default()
+        };
+        assert_eq!(settings.apply(text.to_owned()), "Dubai Marina, ＪＢＲ &amp;nbsp;１２ m²");
+
+        let rule: TextNormalizationOverride =
+            serde_yaml::from_str("{ nfkc: true, decode_entities: true, case_fold: true, strip_invisible: false }").unwrap();
+        let settings = settings.with_override(&rule);
+        assert_eq!(settings.join_separator, " ");
+        assert_eq!(settings.apply(text.to_owned()), "dubai marina,\u{200B} jbr &nbsp;12 m2");
+    }
+}
@@ -0,0 +1,184 @@
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+use super::compiled_rule::CompiledRegexSub;
+use super::defs::{prepare_rx_for_rust, prepare_rx_sub_for_replace, TransformError};
+
+/// One step of `filters` of a rule, steps run in order on the extracted text
+///
+/// ```yaml
+/// filters:
+///   - regex_extract: 'AED ([\d,]+)'
+///   - strip_non_digits
+///   - split: { separator: '/', index: -1 }
+///   - url_join: 'https://www.bayut.com'
+/// ```
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueFilter {
+    /// regex and Python replacement like `regex_sub_value`
+    RegexReplace(String, String),
+    /// first group or the whole match, empty without a match
+    RegexExtract(String),
+    Trim,
+    Lower,
+    Upper,
+    /// part by index, a negative index counts from the end, empty out of range
+    Split { separator: String, index: i64 },
+    StripNonDigits,
+    /// prepended to a non empty value
+    Prefix(String),
+    /// appended to a non empty value
+    Suffix(String),
+    /// resolves a relative url of a non empty value against the base url
+    UrlJoin(String),
+    /// replaces an empty value
+    Default(String),
+}
+
+/// `ValueFilter` with regexes and urls parsed once
+#[derive(Debug, Clone)]
+pub enum CompiledFilter {
+    RegexReplace(CompiledRegexSub),
+    RegexExtract(Regex),
+    Trim,
+    Lower,
+    Upper,
+    Split { separator: String, index: i64 },
+    StripNonDigits,
+    Prefix(String),
+    Suffix(String),
+    UrlJoin(Url),
+    Default(String),
+}
+
+fn compile_regex(regex: &str) -> Result<Regex, TransformError> {
+    Regex::new(&prepare_rx_for_rust(regex)).map_err(|err| TransformError::RegexError {
+        regex: regex.to_owned(),
+        message: err.to_string(),
+    })
+}
+
+impl CompiledFilter {
+    pub fn compile(filter: &ValueFilter) -> Result<Self, TransformError> {
+        Ok(match filter {
+            ValueFilter::RegexReplace(regex, replacement) => CompiledFilter::RegexReplace(CompiledRegexSub {
+                regex: compile_regex(regex)?,
+                replacement: prepare_rx_sub_for_replace(replacement),
+            }),
+            ValueFilter::RegexExtract(regex) => CompiledFilter::RegexExtract(compile_regex(regex)?),
+            ValueFilter::Trim => CompiledFilter::Trim,
+            ValueFilter::Lower => CompiledFilter::Lower,
+            ValueFilter::Upper => CompiledFilter::Upper,
+            ValueFilter::Split { separator, index } => CompiledFilter::Split {
+                separator: separator.clone(),
+                index: *index,
+            },
+            ValueFilter::StripNonDigits => CompiledFilter::StripNonDigits,
+            ValueFilter::Prefix(prefix) => CompiledFilter::Prefix(prefix.clone()),
+            ValueFilter::Suffix(suffix) => CompiledFilter::Suffix(suffix.clone()),
+            ValueFilter::UrlJoin(base) => CompiledFilter::UrlJoin(Url::parse(base).map_err(|err| {
+                TransformError::FilterError {
+                    filter: format!("{filter:?}"),
+                    message: err.to_string(),
+                }
+            })?),
+            ValueFilter::Default(value) => CompiledFilter::Default(value.clone()),
+        })
+    }
+
+    pub fn apply(&self, text: String) -> String {
+        match self {
+            CompiledFilter::RegexReplace(regex_sub) => regex_sub.replace_all(&text),
+            CompiledFilter::RegexExtract(regex) => regex
+                .captures(&text)
+                .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+                .map(|found| found.as_str().to_owned())
+                .unwrap_or_default(),
+            CompiledFilter::Trim => text.trim().to_owned(),
+            CompiledFilter::Lower => text.to_lowercase(),
+            CompiledFilter::Upper => text.to_uppercase(),
+            CompiledFilter::Split { separator, index } => {
+                let parts: Vec<&str> = text.split(separator.as_str()).collect();
+                let index = if *index < 0 {
+                    usize::try_from(index.unsigned_abs())
+                        .ok()
+                        .and_then(|from_end| parts.len().checked_sub(from_end))
+                } else {
+                    usize::try_from(*index).ok()
+                };
+                index
+                    .and_then(|index| parts.get(index))
+                    .map(|part| part.to_string())
+                    .unwrap_or_default()
+            }
+            CompiledFilter::StripNonDigits => text.chars().filter(char::is_ascii_digit).collect(),
+            CompiledFilter::Prefix(prefix) if !text.is_empty() => format!("{prefix}{text}"),
+            CompiledFilter::Suffix(suffix) if !text.is_empty() => format!("{text}{suffix}"),
+            CompiledFilter::UrlJoin(base) if !text.is_empty() => base.join(&text).map(String::from).unwrap_or(text),
+            CompiledFilter::Default(value) if text.is_empty() => value.clone(),
+            CompiledFilter::Prefix(_) | CompiledFilter::Suffix(_) | CompiledFilter::UrlJoin(_) | CompiledFilter::Default(_) => text,
+        }
+    }
+}
+
+pub fn compile_filters(filters: &[ValueFilter]) -> Result<Vec<CompiledFilter>, TransformError> {
+    filters.iter().map(CompiledFilter::compile).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters() {
+        let filters: Vec<ValueFilter> = serde_yaml::with::singleton_map_recursive::deserialize(serde_yaml::Deserializer::from_str(
+            r#"
+- regex_replace: ['\s+', ' ']
+- regex_extract: 'Price: (.*) AED'
+- trim
+- upper
+- split: { separator: ' ', index: -1 }
+- strip_non_digits
+- prefix: '#'
+- suffix: '!'
+"#,
+        ))
+        .unwrap();
+        assert_eq!(filters[2], ValueFilter::Trim);
+        let filters = compile_filters(&filters).unwrap();
+        let apply = |filters: &[CompiledFilter], text: &str| {
+            filters.iter().fold(text.to_owned(), |text, filter| filter.apply(text))
+        };
+        assert_eq!(apply(&filters, "Price:  from\n 1,250,000 AED"), "#1250000!");
+        assert_eq!(apply(&filters, "no price"), "");
+
+        let filters = compile_filters(&[
+            ValueFilter::Split {
+                separator: "/".into(),
+                index: 1,
+            },
+            ValueFilter::Lower,
+            ValueFilter::Default("none".into()),
+        ])
+        .unwrap();
+        assert_eq!(apply(&filters, "/Dubai/Marina"), "dubai");
+        assert_eq!(apply(&filters, "Dubai"), "none");
+
+        let join = compile_filters(&[ValueFilter::UrlJoin("https://www.bayut.com/for-sale/".into())]).unwrap();
+        assert_eq!(apply(&join, "/property/details-1.html"), "https://www.bayut.com/property/details-1.html");
+        assert_eq!(apply(&join, "details-1.html"), "https://www.bayut.com/for-sale/details-1.html");
+        assert_eq!(apply(&join, "https://other.com/a"), "https://other.com/a");
+        assert_eq!(apply(&join, ""), "");
+
+        assert!(matches!(
+            CompiledFilter::compile(&ValueFilter::UrlJoin("not a url".into())),
+            Err(TransformError::FilterError { .. })
+        ));
+        assert!(matches!(
+            CompiledFilter::compile(&ValueFilter::RegexExtract("(unclosed".into())),
+            Err(TransformError::RegexError { .. })
+        ));
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::defs::TransformedData;
+
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// `type` of a rule, the extracted text is converted after `regex_sub_value`
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType {
+    Int,
+    Float,
+    Bool,
+    /// exact number, written to JSON as is
+    Decimal,
+    /// parsed with `date_format`, written as `YYYY-MM-DD`
+    Date,
+}
+
+/// `on_type_error` of a rule, what to do with a text which doesn't convert
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnTypeError {
+    #[default]
+    Null,
+    Error,
+}
+
+/// Thousands separators and spaces are dropped from numbers, `1,250 000` is `1250000`
+fn number_text(text: &str) -> String {
+    text.chars()
+        .filter(|ch| *ch != ',' && !ch.is_whitespace())
+        .collect()
+}
+
+/// Valid JSON number text: no `+`, no leading zeros, digits around the dot
+fn decimal_text(text: &str) -> Option<String> {
+    let text = number_text(text);
+    let (sign, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", text.strip_prefix('+').unwrap_or(&text)),
+    };
+    let (int_part, frac_part) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let is_digits = |part: &str| part.chars().all(|ch| ch.is_ascii_digit());
+    if (int_part.is_empty() && frac_part.is_empty()) || !is_digits(int_part) || !is_digits(frac_part) {
+        return None;
+    }
+    let int_part = match int_part.trim_start_matches('0') {
+        "" => "0",
+        digits => digits,
+    };
+    Some(match frac_part {
+        "" => format!("{sign}{int_part}"),
+        _ => format!("{sign}{int_part}.{frac_part}"),
+    })
+}
+
+fn bool_value(text: &str) -> Option<bool> {
+    match text.to_lowercase().as_str() {
+        "true" | "yes" | "y" | "on" | "1" => Some(true),
+        "false" | "no" | "n" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+impl ValueType {
+    /// None when the text isn't a value of the type
+    pub fn convert(&self, text: &str, date_format: &str) -> Option<TransformedData> {
+        let text = text.trim();
+        match self {
+            ValueType::Int => number_text(text).parse::<i64>().ok().map(TransformedData::Int),
+            ValueType::Float => number_text(text)
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite())
+                .map(TransformedData::Float),
+            ValueType::Bool => bool_value(text).map(TransformedData::Bool),
+            ValueType::Decimal => decimal_text(text).map(TransformedData::Decimal),
+            ValueType::Date => {
+                let date_format = if date_format.is_empty() { DEFAULT_DATE_FORMAT } else { date_format };
+                NaiveDate::parse_from_str(text, date_format).ok().map(TransformedData::Date)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert() {
+        let convert = |value_type: ValueType, text: &str| value_type.convert(text, "");
+        assert_eq!(convert(ValueType::Int, " 1,250 000 "), Some(TransformedData::Int(1_250_000)));
+        assert_eq!(convert(ValueType::Int, "-7"), Some(TransformedData::Int(-7)));
+        assert_eq!(convert(ValueType::Int, "1.5"), None);
+        assert_eq!(convert(ValueType::Int, ""), None);
+        assert_eq!(convert(ValueType::Float, "1,234.5"), Some(TransformedData::Float(1234.5)));
+        assert_eq!(convert(ValueType::Float, "NaN"), None);
+        assert_eq!(convert(ValueType::Bool, "Yes"), Some(TransformedData::Bool(true)));
+        assert_eq!(convert(ValueType::Bool, "0"), Some(TransformedData::Bool(false)));
+        assert_eq!(convert(ValueType::Bool, "maybe"), None);
+        assert_eq!(
+            convert(ValueType::Decimal, "+007,000.1250"),
+            Some(TransformedData::Decimal("7000.1250".into()))
+        );
+        assert_eq!(convert(ValueType::Decimal, "-.5"), Some(TransformedData::Decimal("-0.5".into())));
+        assert_eq!(convert(ValueType::Decimal, "5."), Some(TransformedData::Decimal("5".into())));
+        assert_eq!(convert(ValueType::Decimal, "."), None);
+        assert_eq!(convert(ValueType::Decimal, "1e5"), None);
+        assert_eq!(
+            convert(ValueType::Date, "2024-05-01"),
+            Some(TransformedData::Date(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()))
+        );
+        assert_eq!(
+            ValueType::Date.convert("01 May 2024", "%d %B %Y"),
+            Some(TransformedData::Date(NaiveDate::from_ymd_opt(2024, 5, 1).unwrap()))
+        );
+        assert_eq!(convert(ValueType::Date, "2024-02-30"), None);
+    }
+}
@@ -0,0 +1,764 @@
+use std::collections::{HashMap, HashSet};
+
+use ego_tree::{NodeId, NodeRef};
+use scraper::{ElementRef, Node};
+
+use super::defs::TransformError;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Slash,
+    DoubleSlash,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    At,
+    Comma,
+    Pipe,
+    Dot,
+    DoubleDot,
+    DoubleColon,
+    Star,
+    Op(&'static str),
+    Name(String),
+    Literal(String),
+    Number(f64),
+}
+
+fn selector_error(xpath: &str, message: impl ToString) -> TransformError {
+    TransformError::SelectorError {
+        selector: xpath.to_owned(),
+        message: message.to_string(),
+    }
+}
+
+fn tokenize(xpath: &str) -> Result<Vec<Token>, TransformError> {
+    let mut tokens = Vec::new();
+    let mut rest = xpath;
+    while let Some(ch) = rest.chars().next() {
+        if ch.is_whitespace() {
+            rest = &rest[ch.len_utf8()..];
+            continue;
+        }
+        let two = rest.get(..2).unwrap_or("");
+        let (token, len) = match (ch, two) {
+            (_, "//") => (Token::DoubleSlash, 2),
+            (_, "..") => (Token::DoubleDot, 2),
+            (_, "::") => (Token::DoubleColon, 2),
+            (_, "!=") => (Token::Op("!="), 2),
+            (_, "<=") => (Token::Op("<="), 2),
+            (_, ">=") => (Token::Op(">="), 2),
+            ('/', _) => (Token::Slash, 1),
+            ('[', _) => (Token::LBracket, 1),
+            (']', _) => (Token::RBracket, 1),
+            ('(', _) => (Token::LParen, 1),
+            (')', _) => (Token::RParen, 1),
+            ('@', _) => (Token::At, 1),
+            (',', _) => (Token::Comma, 1),
+            ('|', _) => (Token::Pipe, 1),
+            ('*', _) => (Token::Star, 1),
+            ('=', _) => (Token::Op("="), 1),
+            ('<', _) => (Token::Op("<"), 1),
+            ('>', _) => (Token::Op(">"), 1),
+            ('"' | '\'', _) => {
+                let end = rest[1..]
+                    .find(ch)
+                    .ok_or_else(|| selector_error(xpath, "unclosed string literal"))?;
+                (Token::Literal(rest[1..end + 1].to_owned()), end + 2)
+            }
+            _ if ch.is_ascii_digit() || (ch == '.' && rest[1..].starts_with(|c: char| c.is_ascii_digit())) => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit() && c != '.')
+                    .unwrap_or(rest.len());
+                let number = rest[..len]
+                    .parse::<f64>()
+                    .map_err(|err| selector_error(xpath, err))?;
+                (Token::Number(number), len)
+            }
+            ('.', _) => (Token::Dot, 1),
+            _ if ch.is_alphabetic() || ch == '_' => {
+                let len = rest
+                    .find(|c: char| !(c.is_alphanumeric() || matches!(c, '-' | '_' | '.')))
+                    .unwrap_or(rest.len());
+                (Token::Name(rest[..len].to_owned()), len)
+            }
+            _ => return Err(selector_error(xpath, format!("unexpected [{ch}]"))),
+        };
+        tokens.push(token);
+        rest = &rest[len..];
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Axis {
+    Child,
+    Descendant,
+    DescendantOrSelf,
+    SelfNode,
+    Parent,
+    Ancestor,
+    AncestorOrSelf,
+    FollowingSibling,
+    PrecedingSibling,
+    Attribute,
+}
+
+impl Axis {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "child" => Axis::Child,
+            "descendant" => Axis::Descendant,
+            "descendant-or-self" => Axis::DescendantOrSelf,
+            "self" => Axis::SelfNode,
+            "parent" => Axis::Parent,
+            "ancestor" => Axis::Ancestor,
+            "ancestor-or-self" => Axis::AncestorOrSelf,
+            "following-sibling" => Axis::FollowingSibling,
+            "preceding-sibling" => Axis::PrecedingSibling,
+            "attribute" => Axis::Attribute,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeTest {
+    /// element or attribute name, lower case like names of the parsed HTML
+    Name(String),
+    Any,
+    Text,
+    Node,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    axis: Axis,
+    test: NodeTest,
+    predicates: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+struct LocationPath {
+    absolute: bool,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Contains,
+    StartsWith,
+    Not,
+    NormalizeSpace,
+    String,
+    StringLength,
+    Count,
+    Last,
+    Position,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "contains" => Function::Contains,
+            "starts-with" => Function::StartsWith,
+            "not" => Function::Not,
+            "normalize-space" => Function::NormalizeSpace,
+            "string" => Function::String,
+            "string-length" => Function::StringLength,
+            "count" => Function::Count,
+            "last" => Function::Last,
+            "position" => Function::Position,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Compare(&'static str, Box<Expr>, Box<Expr>),
+    Union(Vec<LocationPath>),
+    Path(LocationPath),
+    Literal(String),
+    Number(f64),
+    Call(Function, Vec<Expr>),
+}
+
+struct Parser<'t> {
+    xpath: &'t str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), TransformError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            other => Err(self.error(format!("expected {expected:?}, got {other:?}"))),
+        }
+    }
+
+    fn error(&self, message: impl ToString) -> TransformError {
+        selector_error(self.xpath, message)
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Name(name)) if name == keyword)
+    }
+
+    fn parse_expr(&mut self) -> Result<Expr, TransformError> {
+        let mut expr = self.parse_and()?;
+        while self.is_keyword("or") {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, TransformError> {
+        let mut expr = self.parse_compare()?;
+        while self.is_keyword("and") {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_compare()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr, TransformError> {
+        let mut expr = self.parse_union()?;
+        while let Some(Token::Op(op)) = self.peek() {
+            let op = *op;
+            self.next();
+            expr = Expr::Compare(op, Box::new(expr), Box::new(self.parse_union()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_union(&mut self) -> Result<Expr, TransformError> {
+        let expr = self.parse_primary()?;
+        if self.peek() != Some(&Token::Pipe) {
+            return Ok(expr);
+        }
+        let Expr::Path(path) = expr else {
+            return Err(self.error("union of a non path expression"));
+        };
+        let mut paths = vec![path];
+        while self.peek() == Some(&Token::Pipe) {
+            self.next();
+            paths.push(self.parse_location_path()?);
+        }
+        Ok(Expr::Union(paths))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, TransformError> {
+        match (self.peek().cloned(), self.peek_at(1).cloned()) {
+            (Some(Token::Literal(text)), _) => {
+                self.next();
+                Ok(Expr::Literal(text))
+            }
+            (Some(Token::Number(number)), _) => {
+                self.next();
+                Ok(Expr::Number(number))
+            }
+            (Some(Token::LParen), _) => {
+                self.next();
+                let expr = self.parse_expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            (Some(Token::Name(name)), Some(Token::LParen)) if name != "text" && name != "node" => {
+                let function = Function::parse(&name)
+                    .ok_or_else(|| self.error(format!("unsupported function [{name}]")))?;
+                self.pos += 2;
+                let mut args = Vec::new();
+                if self.peek() != Some(&Token::RParen) {
+                    args.push(self.parse_expr()?);
+                    while self.peek() == Some(&Token::Comma) {
+                        self.next();
+                        args.push(self.parse_expr()?);
+                    }
+                }
+                self.expect(Token::RParen)?;
+                Ok(Expr::Call(function, args))
+            }
+            _ => Ok(Expr::Path(self.parse_location_path()?)),
+        }
+    }
+
+    fn parse_location_path(&mut self) -> Result<LocationPath, TransformError> {
+        let mut path = LocationPath {
+            absolute: false,
+            steps: Vec::new(),
+        };
+        match self.peek() {
+            Some(Token::Slash) => {
+                self.next();
+                path.absolute = true;
+                if !self.starts_step() {
+                    return Ok(path);
+                }
+            }
+            Some(Token::DoubleSlash) => {
+                self.next();
+                path.absolute = true;
+                path.steps.push(descendant_or_self());
+            }
+            _ => {}
+        }
+        loop {
+            path.steps.push(self.parse_step()?);
+            match self.peek() {
+                Some(Token::Slash) => {
+                    self.next();
+                }
+                Some(Token::DoubleSlash) => {
+                    self.next();
+                    path.steps.push(descendant_or_self());
+                }
+                _ => return Ok(path),
+            }
+        }
+    }
+
+    fn starts_step(&self) -> bool {
+        matches!(
+            self.peek(),
+            Some(Token::Name(_) | Token::Star | Token::At | Token::Dot | Token::DoubleDot)
+        )
+    }
+
+    fn parse_step(&mut self) -> Result<Step, TransformError> {
+        let (axis, test) = match self.next() {
+            Some(Token::Dot) => (Axis::SelfNode, NodeTest::Node),
+            Some(Token::DoubleDot) => (Axis::Parent, NodeTest::Node),
+            Some(Token::At) => (Axis::Attribute, self.parse_node_test()?),
+            Some(Token::Name(name)) if self.peek() == Some(&Token::DoubleColon) => {
+                let axis = Axis::parse(&name).ok_or_else(|| self.error(format!("unsupported axis [{name}]")))?;
+                self.next();
+                (axis, self.parse_node_test()?)
+            }
+            Some(Token::Name(_) | Token::Star) => {
+                self.pos -= 1;
+                (Axis::Child, self.parse_node_test()?)
+            }
+            other => return Err(self.error(format!("expected a step, got {other:?}"))),
+        };
+        let mut predicates = Vec::new();
+        while self.peek() == Some(&Token::LBracket) {
+            self.next();
+            predicates.push(self.parse_expr()?);
+            self.expect(Token::RBracket)?;
+        }
+        Ok(Step { axis, test, predicates })
+    }
+
+    fn parse_node_test(&mut self) -> Result<NodeTest, TransformError> {
+        match self.next() {
+            Some(Token::Star) => Ok(NodeTest::Any),
+            Some(Token::Name(name)) if self.peek() == Some(&Token::LParen) => {
+                self.next();
+                self.expect(Token::RParen)?;
+                match name.as_str() {
+                    "text" => Ok(NodeTest::Text),
+                    "node" => Ok(NodeTest::Node),
+                    _ => Err(self.error(format!("unsupported node test [{name}()]"))),
+                }
+            }
+            Some(Token::Name(name)) => Ok(NodeTest::Name(name.to_lowercase())),
+            other => Err(self.error(format!("expected a node test, got {other:?}"))),
+        }
+    }
+}
+
+fn descendant_or_self() -> Step {
+    Step {
+        axis: Axis::DescendantOrSelf,
+        test: NodeTest::Node,
+        predicates: Vec::new(),
+    }
+}
+
+/// Node of the document or an attribute value
+#[derive(Debug, Clone, Copy)]
+enum Item<'a> {
+    Node(NodeRef<'a, Node>),
+    Attr(&'a str),
+}
+
+impl<'a> Item<'a> {
+    fn string_value(&self) -> String {
+        match self {
+            Item::Node(node) => node
+                .descendants()
+                .filter_map(|node| node.value().as_text())
+                .map(|text| &**text)
+                .collect(),
+            Item::Attr(value) => value.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value<'a> {
+    Items(Vec<Item<'a>>),
+    Str(String),
+    Num(f64),
+    Bool(bool),
+}
+
+fn string_to_number(text: &str) -> f64 {
+    text.trim().parse::<f64>().unwrap_or(f64::NAN)
+}
+
+impl<'a> Value<'a> {
+    fn to_bool(&self) -> bool {
+        match self {
+            Value::Items(items) => !items.is_empty(),
+            Value::Str(text) => !text.is_empty(),
+            Value::Num(number) => *number != 0.0 && !number.is_nan(),
+            Value::Bool(value) => *value,
+        }
+    }
+
+    fn to_string_value(&self) -> String {
+        match self {
+            Value::Items(items) => items.first().map(Item::string_value).unwrap_or_default(),
+            Value::Str(text) => text.clone(),
+            Value::Num(number) if number.fract() == 0.0 && number.is_finite() => format!("{number:.0}"),
+            Value::Num(number) => number.to_string(),
+            Value::Bool(value) => value.to_string(),
+        }
+    }
+
+    fn to_number(&self) -> f64 {
+        match self {
+            Value::Num(number) => *number,
+            Value::Bool(value) => f64::from(u8::from(*value)),
+            other => string_to_number(&other.to_string_value()),
+        }
+    }
+}
+
+fn compare_atoms(op: &str, left: &Value, right: &Value) -> bool {
+    if matches!(op, "=" | "!=") {
+        let equal = match (left, right) {
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => left.to_bool() == right.to_bool(),
+            (Value::Num(_), _) | (_, Value::Num(_)) => left.to_number() == right.to_number(),
+            _ => left.to_string_value() == right.to_string_value(),
+        };
+        return equal == (op == "=");
+    }
+    let (left, right) = (left.to_number(), right.to_number());
+    match op {
+        "<" => left < right,
+        "<=" => left <= right,
+        ">" => left > right,
+        _ => left >= right,
+    }
+}
+
+/// Node sets compare by any of their string values
+fn compare(op: &str, left: &Value, right: &Value) -> bool {
+    let atoms = |value: &Value<'_>| -> Vec<Value<'static>> {
+        match value {
+            Value::Items(items) => items.iter().map(|item| Value::Str(item.string_value())).collect(),
+            Value::Str(text) => vec![Value::Str(text.clone())],
+            Value::Num(number) => vec![Value::Num(*number)],
+            Value::Bool(value) => vec![Value::Bool(*value)],
+        }
+    };
+    if let (Value::Items(_), Value::Bool(_)) | (Value::Bool(_), Value::Items(_)) = (left, right) {
+        return compare_atoms(op, &Value::Bool(left.to_bool()), &Value::Bool(right.to_bool()));
+    }
+    let (left, right) = (atoms(left), atoms(right));
+    left.iter().any(|l| right.iter().any(|r| compare_atoms(op, l, r)))
+}
+
+struct Context<'a> {
+    item: Item<'a>,
+    position: usize,
+    size: usize,
+}
+
+fn axis_items<'a>(item: Item<'a>, axis: Axis, test: &NodeTest) -> Vec<Item<'a>> {
+    let Item::Node(node) = item else {
+        return match (axis, test) {
+            (Axis::SelfNode, NodeTest::Node) => vec![item],
+            _ => Vec::new(),
+        };
+    };
+    if axis == Axis::Attribute {
+        let Some(element) = node.value().as_element() else {
+            return Vec::new();
+        };
+        return element
+            .attrs()
+            .filter(|(name, _)| match test {
+                NodeTest::Name(test_name) => name == test_name,
+                NodeTest::Any | NodeTest::Node => true,
+                NodeTest::Text => false,
+            })
+            .map(|(_, value)| Item::Attr(value))
+            .collect();
+    }
+    let nodes: Vec<NodeRef<'a, Node>> = match axis {
+        Axis::Child => node.children().collect(),
+        Axis::Descendant => node.descendants().skip(1).collect(),
+        Axis::DescendantOrSelf => node.descendants().collect(),
+        Axis::SelfNode => vec![node],
+        Axis::Parent => node.parent().into_iter().collect(),
+        Axis::Ancestor => node.ancestors().collect(),
+        Axis::AncestorOrSelf => std::iter::once(node).chain(node.ancestors()).collect(),
+        Axis::FollowingSibling => node.next_siblings().collect(),
+        Axis::PrecedingSibling => node.prev_siblings().collect(),
+        Axis::Attribute => unreachable!(),
+    };
+    nodes
+        .into_iter()
+        .filter(|node| match (test, node.value()) {
+            (NodeTest::Name(name), Node::Element(element)) => element.name() == name,
+            (NodeTest::Any, Node::Element(_)) => true,
+            (NodeTest::Text, Node::Text(_)) => true,
+            (NodeTest::Node, Node::Element(_) | Node::Text(_) | Node::Document | Node::Fragment) => true,
+            _ => false,
+        })
+        .map(Item::Node)
+        .collect()
+}
+
+fn item_key(item: &Item) -> Option<NodeId> {
+    match item {
+        Item::Node(node) => Some(node.id()),
+        Item::Attr(_) => None,
+    }
+}
+
+fn eval_path<'a>(path: &LocationPath, context: Item<'a>) -> Vec<Item<'a>> {
+    let mut current = match (path.absolute, context) {
+        (true, Item::Node(node)) => vec![Item::Node(node.tree().root())],
+        (true, Item::Attr(_)) => Vec::new(),
+        (false, _) => vec![context],
+    };
+    for step in path.steps.iter() {
+        let mut seen = HashSet::new();
+        let mut next = Vec::new();
+        for item in current {
+            // positions follow the axis direction, nearest first on reverse axes
+            let mut candidates = axis_items(item, step.axis, &step.test);
+            for predicate in step.predicates.iter() {
+                let size = candidates.len();
+                candidates = candidates
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(idx, candidate)| {
+                        let context = Context {
+                            item: *candidate,
+                            position: idx + 1,
+                            size,
+                        };
+                        match eval(predicate, &context) {
+                            Value::Num(number) => number == (idx + 1) as f64,
+                            value => value.to_bool(),
+                        }
+                    })
+                    .map(|(_, candidate)| candidate)
+                    .collect();
+            }
+            next.extend(
+                candidates
+                    .into_iter()
+                    .filter(|candidate| match item_key(candidate) {
+                        Some(id) => seen.insert(id),
+                        None => true,
+                    }),
+            );
+        }
+        current = next;
+    }
+    current
+}
+
+fn eval<'a>(expr: &Expr, context: &Context<'a>) -> Value<'a> {
+    let string_arg = |args: &[Expr], idx: usize| match args.get(idx) {
+        Some(arg) => eval(arg, context).to_string_value(),
+        None => context.item.string_value(),
+    };
+    match expr {
+        Expr::Or(left, right) => Value::Bool(eval(left, context).to_bool() || eval(right, context).to_bool()),
+        Expr::And(left, right) => Value::Bool(eval(left, context).to_bool() && eval(right, context).to_bool()),
+        Expr::Compare(op, left, right) => Value::Bool(compare(op, &eval(left, context), &eval(right, context))),
+        Expr::Union(paths) => Value::Items(paths.iter().flat_map(|path| eval_path(path, context.item)).collect()),
+        Expr::Path(path) => Value::Items(eval_path(path, context.item)),
+        Expr::Literal(text) => Value::Str(text.clone()),
+        Expr::Number(number) => Value::Num(*number),
+        Expr::Call(function, args) => match function {
+            Function::Contains => Value::Bool(string_arg(args, 0).contains(&string_arg(args, 1))),
+            Function::StartsWith => Value::Bool(string_arg(args, 0).starts_with(&string_arg(args, 1))),
+            Function::Not => Value::Bool(!args.first().is_some_and(|arg| eval(arg, context).to_bool())),
+            Function::NormalizeSpace => {
+                Value::Str(string_arg(args, 0).split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            Function::String => Value::Str(string_arg(args, 0)),
+            Function::StringLength => Value::Num(string_arg(args, 0).chars().count() as f64),
+            Function::Count => Value::Num(match args.first().map(|arg| eval(arg, context)) {
+                Some(Value::Items(items)) => items.len() as f64,
+                _ => 0.0,
+            }),
+            Function::Last => Value::Num(context.size as f64),
+            Function::Position => Value::Num(context.position as f64),
+        },
+    }
+}
+
+/// XPath 1.0 subset selecting elements: abbreviated and axis steps, `|` unions, predicates
+/// with positions, comparisons, `and`/`or` and `contains`, `starts-with`, `not`,
+/// `normalize-space`, `string`, `string-length`, `count`, `last`, `position`
+#[derive(Debug, Clone)]
+pub struct XPathSelector {
+    paths: Vec<LocationPath>,
+}
+
+impl XPathSelector {
+    pub fn parse(xpath: &str) -> Result<Self, TransformError> {
+        let mut parser = Parser {
+            xpath,
+            tokens: tokenize(xpath)?,
+            pos: 0,
+        };
+        let paths = match parser.parse_expr()? {
+            Expr::Path(path) => vec![path],
+            Expr::Union(paths) => paths,
+            _ => return Err(selector_error(xpath, "xpath must be a location path")),
+        };
+        if let Some(token) = parser.peek() {
+            return Err(selector_error(xpath, format!("unexpected {token:?}")));
+        }
+        let selects_values = paths.iter().any(|path| {
+            path.steps
+                .last()
+                .is_some_and(|step| step.axis == Axis::Attribute || step.test == NodeTest::Text)
+        });
+        if selects_values {
+            return Err(selector_error(
+                xpath,
+                "xpath must select elements, use attribute_name for attributes",
+            ));
+        }
+        Ok(Self { paths })
+    }
+
+    /// Matching elements in document order, relative paths start at the element
+    pub fn select<'a>(&self, soup: ElementRef<'a>) -> Vec<ElementRef<'a>> {
+        let mut seen = HashSet::new();
+        let mut found: Vec<ElementRef<'a>> = self
+            .paths
+            .iter()
+            .flat_map(|path| eval_path(path, Item::Node(*soup)))
+            .filter_map(|item| match item {
+                Item::Node(node) => ElementRef::wrap(node),
+                Item::Attr(_) => None,
+            })
+            .filter(|ele| seen.insert(ele.id()))
+            .collect();
+        if found.len() > 1 {
+            // only walk the context subtree unless an axis like `..` left it
+            let in_soup = found
+                .iter()
+                .all(|ele| ele.id() == soup.id() || ele.ancestors().any(|node| node.id() == soup.id()));
+            let scope = if in_soup { *soup } else { soup.tree().root() };
+            let order: HashMap<NodeId, usize> = scope
+                .descendants()
+                .filter(|node| seen.contains(&node.id()))
+                .take(seen.len())
+                .enumerate()
+                .map(|(idx, node)| (node.id(), idx))
+                .collect();
+            found.sort_by_key(|ele| order.get(&ele.id()).copied().unwrap_or(usize::MAX));
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use scraper::Html;
+
+    use super::*;
+
+    fn select_html(html: &Html, xpath: &str) -> Vec<String> {
+        XPathSelector::parse(xpath)
+            .unwrap()
+            .select(html.root_element())
+            .iter()
+            .map(|tag| tag.text().collect::<String>().trim().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_xpath() {
+        let html = Html::parse_document(
+            r#"
+            <table id="facts">
+              <tr><td class="label">Property type</td><td>Villa</td></tr>
+              <tr><td class="label"> Bedrooms </td><td>4</td><td>rooms</td></tr>
+              <tr><td class="label">Price</td><td data-currency="AED">1,250,000</td></tr>
+            </table>
+            <ul><li>a</li><li>b</li><li>c</li></ul>
+            "#,
+        );
+        assert_eq!(
+            select_html(&html, "//td[normalize-space(.)='Bedrooms']/following-sibling::td[1]"),
+            vec!["4"]
+        );
+        assert_eq!(select_html(&html, "//td[@data-currency='AED']"), vec!["1,250,000"]);
+        assert_eq!(select_html(&html, "//tr[td[@class='label']='Price']/td[2]"), vec!["1,250,000"]);
+        assert_eq!(select_html(&html, "//ul/li[last()]"), vec!["c"]);
+        assert_eq!(select_html(&html, "//ul/li[position() > 1]"), vec!["b", "c"]);
+        assert_eq!(select_html(&html, "(//ul/li)"), vec!["a", "b", "c"]);
+        assert_eq!(select_html(&html, "//li[3] | //li[1]"), vec!["a", "c"]);
+        assert_eq!(
+            select_html(&html, "//td[contains(text(), 'rooms') and not(@class)]/preceding-sibling::td[last()]"),
+            vec!["Bedrooms"]
+        );
+        assert_eq!(select_html(&html, "//td[starts-with(., 'Pro')]/.."), vec!["Property typeVilla"]);
+        assert_eq!(select_html(&html, "//tr[count(td) = 3]/td[3]"), vec!["rooms"]);
+        assert_eq!(select_html(&html, "//li[string-length(.) = 1][2]"), vec!["b"]);
+        assert_eq!(select_html(&html, "//table[@id = \"facts\"]//td[. = 4]"), vec!["4"]);
+
+        let table = XPathSelector::parse("//table").unwrap().select(html.root_element())[0];
+        let relative = XPathSelector::parse(".//td[1]").unwrap();
+        assert_eq!(relative.select(table).len(), 3);
+        let absolute = XPathSelector::parse("//li").unwrap();
+        assert_eq!(absolute.select(table).len(), 3);
+        let row = XPathSelector::parse("//tr[2]").unwrap().select(html.root_element())[0];
+        let around = XPathSelector::parse("td[2] | .. | ./td[1]").unwrap();
+        let texts: Vec<_> = around.select(row).iter().map(|tag| tag.text().collect::<String>()).collect();
+        assert_eq!(texts.len(), 3);
+        assert!(texts[0].contains("Property type"), "{texts:?}");
+        assert_eq!(texts[1..], [" Bedrooms ", "4"]);
+
+        for xpath in ["//td/@class", "//td/text()", "//td[", "count(//td)", "//td[unknown()]", "bogus::td"] {
+            assert!(
+                matches!(XPathSelector::parse(xpath), Err(TransformError::SelectorError { .. })),
+                "{xpath}"
+            );
+        }
+    }
+}